@@ -25,7 +25,7 @@
             just
             (rust-bin.stable."1.73.0".default.override {
               extensions = [ "rust-src" "rust-analyzer" ];
-              targets = ["x86_64-unknown-linux-gnu"];
+              targets = ["x86_64-unknown-linux-gnu" "x86_64-pc-windows-gnu"];
             })
           ];
           RUST_SRC_PATH = "${rust-bin.stable."1.73.0".default}/lib/rustlib/src/rust/library";
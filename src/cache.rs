@@ -0,0 +1,418 @@
+//! A content addressed cache of compiled units shared between projects and
+//! builds. An entry is keyed by a SHA-256 hash of the rustc command line, the
+//! rustc version, every source file and environment variable rustc reads and
+//! the libraries passed with `--extern`, and holds what rustc wrote and
+//! printed. Paths in the key are relative to the target directory or the
+//! project root so that checkouts in different places share entries. The
+//! least recently used entries are evicted once the cache is over its size
+//! limit.
+use super::FreightError;
+use super::Result;
+use crate::fingerprint;
+use crate::rustc::RustcVersion;
+use crate::sha256::Sha256;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Environment variables that change what rustc does without showing up in
+/// the command line or the dep-info file
+const ENV: &[&str] = &["RUSTC_BOOTSTRAP"];
+
+/// The file in an entry holding what rustc printed
+const DIAGNOSTICS: &str = ".diagnostics";
+/// What the project root is replaced with in stored diagnostics
+const ROOT: &str = "<freight-root>";
+/// The file in an entry holding when it was last used, for eviction
+const LAST_USED: &str = ".last-used";
+/// The file in an entry listing everywhere its outputs were written, which
+/// keeps it around for `freight clean --gc` while any of them still exist
+const OUTPUTS: &str = ".outputs";
+
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+    /// The rustc version so upgrading the toolchain misses
+    rustc: String,
+    /// Where the project being built is, which paths in keys are relative to
+    root: PathBuf,
+    target_dir: PathBuf,
+}
+
+/// How much is in the cache and how often it has been useful
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub entries: usize,
+    pub size: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>, max_size: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size,
+            rustc: String::new(),
+            root: PathBuf::new(),
+            target_dir: PathBuf::new(),
+        }
+    }
+
+    /// Set the rustc units are compiled with, which is part of every key
+    pub fn rustc(mut self, rustc: &RustcVersion) -> Self {
+        self.rustc = format!(
+            "{} {} {} {}",
+            rustc.version,
+            rustc.commit_hash.as_deref().unwrap_or_default(),
+            rustc.host,
+            rustc.llvm_version.as_deref().unwrap_or_default()
+        );
+        self
+    }
+
+    /// Set the project root and target directory of the build, which the
+    /// paths in keys and stored diagnostics are made relative to
+    pub fn paths(mut self, root: &Path, target_dir: &Path) -> Self {
+        self.root = root.to_path_buf();
+        self.target_dir = target_dir.to_path_buf();
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// Work out the key for a unit by having rustc write only its dep-info
+    /// with `dep_info_command`. This is `None` if rustc can't, like when the
+    /// code doesn't parse, in which case the unit is compiled as normal.
+    pub fn key(
+        &self,
+        command: &Command,
+        dep_info_command: &mut Command,
+        dep_info: &Path,
+    ) -> Option<String> {
+        let status = dep_info_command
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .ok()?;
+        if !status.success() {
+            return None;
+        }
+        let dep_info = fs::read_to_string(dep_info).ok()?;
+
+        let mut sha = Sha256::new();
+        sha.field(&self.rustc);
+        sha.field(self.portable_arg(command.get_program()));
+        let mut args = command.get_args();
+        while let Some(arg) = args.next() {
+            sha.field(self.portable_arg(arg));
+            // The libraries depended on are inputs too
+            if arg == "--extern" {
+                let arg = args.next()?;
+                sha.field(self.portable_arg(arg));
+                if let Some((_, path)) = arg.to_str()?.split_once('=') {
+                    sha.field(fs::read(path).ok()?);
+                }
+            }
+        }
+        for (key, value) in command.get_envs() {
+            sha.field(key.to_str()?);
+            sha.field(
+                value
+                    .map(|value| self.portable_arg(value))
+                    .unwrap_or_default(),
+            );
+        }
+        for name in ENV {
+            sha.field(env::var(name).unwrap_or_default());
+        }
+        for line in dep_info.lines() {
+            if let Some(env) = line.strip_prefix("# env-dep:") {
+                sha.field(env);
+            }
+        }
+        for source in fingerprint::parse_dep_info(&dep_info) {
+            sha.field(self.portable_path(&source));
+            sha.field(fs::read(&source).ok()?);
+        }
+        Some(sha.finish())
+    }
+
+    /// A path the same way in every checkout of a project: relative to the
+    /// target directory if it's in there, otherwise to the project root if
+    /// it's in the project or next to it, like a path dependency. Anything
+    /// further away, like the toolchain, stays absolute.
+    fn portable_path(&self, path: &Path) -> String {
+        if let Ok(rest) = path.strip_prefix(&self.target_dir) {
+            return format!("<target>/{}", rest.display());
+        }
+        if let Ok(rest) = path.strip_prefix(&self.root) {
+            return format!("<root>/{}", rest.display());
+        }
+        match self.root.parent().map(|parent| path.strip_prefix(parent)) {
+            Some(Ok(rest)) => format!("<root>/../{}", rest.display()),
+            _ => path.display().to_string(),
+        }
+    }
+
+    /// An argument with the absolute paths in it made portable. Paths can be
+    /// the whole argument or follow an `=`, in a comma separated list like
+    /// `--emit=dep-info=PATH,link`.
+    fn portable_arg(&self, arg: &OsStr) -> String {
+        let arg = arg.to_string_lossy();
+        arg.split(',')
+            .map(|piece| {
+                let start = std::iter::once(0)
+                    .chain(piece.match_indices('=').map(|(index, _)| index + 1))
+                    .find(|start| Path::new(&piece[*start..]).is_absolute());
+                match start {
+                    Some(start) => format!(
+                        "{}{}",
+                        &piece[..start],
+                        self.portable_path(Path::new(&piece[start..]))
+                    ),
+                    None => piece.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join("entries").join(key)
+    }
+
+    /// Copy the outputs of the entry for `key` to where rustc would have
+    /// written them, returning what rustc printed. This is `None` on a miss.
+    pub fn restore(&self, key: &str, outputs: &[PathBuf]) -> Option<Vec<String>> {
+        let entry = self.entry(key);
+        let diagnostics = fs::read_to_string(entry.join(DIAGNOSTICS)).ok()?;
+        for output in outputs {
+            fs::copy(entry.join(output.file_name()?), output).ok()?;
+        }
+        let _ = fs::write(entry.join(LAST_USED), now().to_string());
+        let _ = record_outputs(&entry, outputs);
+        // Diagnostics point at the sources of the checkout being built
+        let root = self.root.display().to_string();
+        Some(
+            diagnostics
+                .lines()
+                .map(|line| line.replace(ROOT, &root))
+                .collect(),
+        )
+    }
+
+    /// Save the outputs of a unit rustc compiled along with what it printed
+    pub fn store(&self, key: &str, outputs: &[PathBuf], lines: &[String]) -> Result<()> {
+        let entry = self.entry(key);
+        if entry.exists() {
+            return Ok(());
+        }
+        // Write to a temporary folder first so that a build that's stopped
+        // halfway can't leave an incomplete entry behind
+        let tmp = self
+            .dir
+            .join("tmp")
+            .join(format!("{key}-{}", std::process::id()));
+        fs::create_dir_all(&tmp).map_err(FreightError::io(&tmp))?;
+        for output in outputs {
+            let Some(file_name) = output.file_name() else {
+                continue;
+            };
+            fs::copy(output, tmp.join(file_name)).map_err(FreightError::io(output))?;
+        }
+        let mut diagnostics = lines.join("\n");
+        if !self.root.as_os_str().is_empty() {
+            diagnostics = diagnostics.replace(&self.root.display().to_string(), ROOT);
+        }
+        fs::write(tmp.join(DIAGNOSTICS), diagnostics).map_err(FreightError::io(&tmp))?;
+        fs::write(tmp.join(LAST_USED), now().to_string()).map_err(FreightError::io(&tmp))?;
+        record_outputs(&tmp, outputs).map_err(FreightError::io(&tmp))?;
+        let entries = self.dir.join("entries");
+        fs::create_dir_all(&entries).map_err(FreightError::io(&entries))?;
+        if fs::rename(&tmp, &entry).is_err() {
+            // Another build stored the same entry first
+            let _ = fs::remove_dir_all(&tmp);
+        }
+        Ok(())
+    }
+
+    /// Add the hits and misses of a build to the totals and remove the least
+    /// recently used entries until the cache fits in its size limit
+    pub fn finish(&self, hits: u64, misses: u64) -> Result<()> {
+        if hits == 0 && misses == 0 {
+            return Ok(());
+        }
+        let stats = self.stats()?;
+        let path = self.dir.join("stats");
+        fs::create_dir_all(&self.dir).map_err(FreightError::io(&self.dir))?;
+        fs::write(
+            &path,
+            format!(
+                "hits {}\nmisses {}\n",
+                stats.hits + hits,
+                stats.misses + misses
+            ),
+        )
+        .map_err(FreightError::io(path))?;
+        if stats.size <= self.max_size {
+            return Ok(());
+        }
+
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|(_, _, last_used)| *last_used);
+        let mut size = stats.size;
+        for (entry, entry_size, _) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            fs::remove_dir_all(&entry).map_err(FreightError::io(&entry))?;
+            size -= entry_size;
+        }
+        Ok(())
+    }
+
+    /// The entries whose outputs have been deleted from every target
+    /// directory they were written to
+    pub fn unreferenced(&self) -> Result<Vec<PathBuf>> {
+        let mut unreferenced = Vec::new();
+        for (entry, _, _) in self.entries()? {
+            let outputs = fs::read_to_string(entry.join(OUTPUTS)).unwrap_or_default();
+            if !outputs.lines().any(|output| Path::new(output).exists()) {
+                unreferenced.push(entry);
+            }
+        }
+        unreferenced.sort();
+        Ok(unreferenced)
+    }
+
+    pub fn stats(&self) -> Result<Stats> {
+        let entries = self.entries()?;
+        let mut stats = Stats {
+            entries: entries.len(),
+            size: entries.iter().map(|(_, size, _)| size).sum(),
+            ..Stats::default()
+        };
+        if let Ok(contents) = fs::read_to_string(self.dir.join("stats")) {
+            for line in contents.lines() {
+                match line.split_once(' ') {
+                    Some(("hits", hits)) => stats.hits = hits.parse().unwrap_or(0),
+                    Some(("misses", misses)) => stats.misses = misses.parse().unwrap_or(0),
+                    _ => {}
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Every entry with its size and when it was last used
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, u64)>> {
+        let dir = self.dir.join("entries");
+        let Ok(read_dir) = fs::read_dir(&dir) else {
+            return Ok(Vec::new());
+        };
+        let mut entries = Vec::new();
+        for entry in read_dir {
+            let entry = entry.map_err(FreightError::io(&dir))?.path();
+            let mut size = 0;
+            for file in fs::read_dir(&entry).map_err(FreightError::io(&entry))? {
+                let file = file.map_err(FreightError::io(&entry))?;
+                size += file
+                    .metadata()
+                    .map_err(FreightError::io(file.path()))?
+                    .len();
+            }
+            let last_used = fs::read_to_string(entry.join(LAST_USED))
+                .ok()
+                .and_then(|last_used| last_used.trim().parse().ok())
+                .unwrap_or(0);
+            entries.push((entry, size, last_used));
+        }
+        Ok(entries)
+    }
+}
+
+/// Add `outputs` to the places an entry's outputs were written
+fn record_outputs(entry: &Path, outputs: &[PathBuf]) -> io::Result<()> {
+    let path = entry.join(OUTPUTS);
+    let mut recorded = fs::read_to_string(&path).unwrap_or_default();
+    for output in outputs {
+        let output = output.display().to_string();
+        if !recorded.lines().any(|line| line == output) {
+            recorded.push_str(&output);
+            recorded.push('\n');
+        }
+    }
+    fs::write(path, recorded)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// Parse a size like `512`, `100M` or `10GiB` into bytes
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = number.parse::<u64>().ok()?;
+    let shift = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        "T" | "TB" | "TIB" => 40,
+        _ => return None,
+    };
+    number.checked_mul(1 << shift)
+}
+
+/// Show a number of bytes in the biggest unit it's at least one of, like
+/// `1.5 MiB`
+pub fn format_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut unit = 0;
+    while unit + 1 < units.len() && size >= 1 << (10 * (unit + 1)) {
+        unit += 1;
+    }
+    if unit == 0 {
+        return format!("{size} B");
+    }
+    format!(
+        "{:.1} {}",
+        size as f64 / (1u64 << (10 * unit)) as f64,
+        units[unit]
+    )
+}
+
+#[test]
+fn sizes() {
+    assert_eq!(parse_size("512"), Some(512));
+    assert_eq!(parse_size("100M"), Some(100 << 20));
+    assert_eq!(parse_size("10GiB"), Some(10 << 30));
+    assert_eq!(parse_size("2 kb"), Some(2048));
+    assert_eq!(parse_size("ten"), None);
+    assert_eq!(parse_size("10X"), None);
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(3 << 19), "1.5 MiB");
+    assert_eq!(format_size(10 << 30), "10.0 GiB");
+}
@@ -1,37 +1,1038 @@
-use super::BoxError;
 use super::Edition;
+use super::FreightError;
 use super::Result;
+use crate::cache;
+use crate::error::did_you_mean;
+use crate::rustc::CrateType;
+use crate::semver::Version;
+use crate::semver::VersionReq;
+use crate::toml;
+use crate::toml::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
 use std::fs;
 use std::path::Path;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 #[derive(Debug)]
 pub struct Manifest {
     pub crate_name: String,
     pub edition: Edition,
+    /// The package's version, which dependents can put requirements on
+    pub version: Option<Version>,
+    /// The oldest rustc the package builds with
+    pub rust_version: Option<Version>,
+    /// The native library the package links to. Only one package in a graph
+    /// can link to each native library.
+    pub links: Option<String>,
+    pub lib: Lib,
+    pub dependencies: Vec<Dependency>,
+}
+
+/// Settings from the `[lib]` table
+#[derive(Debug)]
+pub struct Lib {
+    /// The kinds of library to produce. `proc-macro = true` is the same as
+    /// `crate-type = ["proc-macro"]`.
+    pub crate_type: Vec<CrateType>,
+    /// The library's crate name if it differs from the package name
+    pub name: Option<String>,
+    /// The library's crate root relative to the package if it isn't
+    /// `src/lib.rs`
+    pub path: Option<PathBuf>,
+}
+
+impl Default for Lib {
+    fn default() -> Self {
+        Self {
+            crate_type: vec![CrateType::Lib],
+            name: None,
+            path: None,
+        }
+    }
+}
+
+/// An entry in the `[dependencies]` table. Only path dependencies are supported.
+#[derive(Debug)]
+pub struct Dependency {
+    pub name: String,
+    /// The directory containing the dependency's `Freight.toml`, relative to
+    /// the package depending on it
+    pub path: PathBuf,
+    /// The versions of the package at `path` that are accepted
+    pub version: Option<VersionReq>,
 }
 
 impl Manifest {
     pub fn parse_from_file(path: impl AsRef<Path>) -> Result<Self> {
         let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(FreightError::io(path))?;
+        let (table, spans) = toml::parse(&contents)
+            .map_err(|e| FreightError::manifest(path, &contents, Some(e.span), e.message, None))?;
+        Self::parse(table).map_err(|invalid| invalid.into_error(path, &contents, &spans))
+    }
+
+    fn parse(table: toml::Table) -> std::result::Result<Self, Invalid> {
+        const FIELDS: &[&str] = &[
+            "name",
+            "edition",
+            "version",
+            "rust-version",
+            "links",
+            "lib",
+            "dependencies",
+        ];
         let mut crate_name = None;
         let mut edition = None;
+        let mut version = None;
+        let mut rust_version = None;
+        let mut links = None;
+        let mut lib = Lib::default();
+        let mut dependencies = Vec::new();
+
+        for (field, value) in table {
+            match field.as_str() {
+                "name" => crate_name = Some(expect_str(&field, &value)?.to_string()),
+                "edition" => {
+                    let edition_str = expect_str(&field, &value)?;
+                    edition = Some(Edition::from_str(edition_str).map_err(|message| {
+                        let editions = Edition::ALL
+                            .iter()
+                            .map(|edition| format!("`{edition}`"))
+                            .collect::<Vec<_>>();
+                        Invalid::value("edition", message)
+                            .help(format!("supported editions are {}", editions.join(", ")))
+                    })?);
+                }
+                "version" => {
+                    version = Some(
+                        Version::from_str(expect_str(&field, &value)?)
+                            .map_err(|message| Invalid::value("version", message))?,
+                    )
+                }
+                "rust-version" => {
+                    let input = expect_str(&field, &value)?;
+                    // Only releases are allowed, with or without the patch
+                    let release = match input.split('.').count() {
+                        2 => format!("{input}.0"),
+                        _ => input.to_string(),
+                    };
+                    rust_version = Some(
+                        Version::from_str(&release)
+                            .ok()
+                            .filter(|version| version.pre.is_empty() && !release.contains('+'))
+                            .ok_or_else(|| {
+                                Invalid::value(
+                                    "rust-version",
+                                    format!("`{input}` is not a valid rust-version, expected something like `1.70`"),
+                                )
+                            })?,
+                    );
+                }
+                "links" => links = Some(expect_str(&field, &value)?.to_string()),
+                "lib" => lib = Lib::parse(&value)?,
+                "dependencies" => {
+                    let Value::Table(table) = value else {
+                        return Err(Invalid::value(
+                            "dependencies",
+                            "dependencies must be a table",
+                        ));
+                    };
+                    for (name, value) in table {
+                        let dependency = Dependency::parse(name, value)?;
+                        if let Some(other) = dependencies
+                            .iter()
+                            .find(|other: &&Dependency| other.path == dependency.path)
+                        {
+                            return Err(Invalid::value(
+                                format!("dependencies.{}.path", dependency.name),
+                                format!(
+                                    "Dependencies {} and {} conflict, they both point to {}",
+                                    other.name,
+                                    dependency.name,
+                                    dependency.path.display()
+                                ),
+                            )
+                            .help("a package can only be depended on once"));
+                        }
+                        dependencies.push(dependency);
+                    }
+                }
+                field => return Err(Invalid::unknown_field(field, "", FIELDS)),
+            }
+        }
+
+        Ok(Self {
+            crate_name: crate_name.ok_or("name is a required field")?,
+            edition: edition.ok_or("edition is a required field")?,
+            version,
+            rust_version,
+            links,
+            lib,
+            dependencies,
+        })
+    }
+}
 
-        for line in fs::read_to_string(path)?.lines() {
-            let mut split = line.split('=');
-            let field = split.next().unwrap().trim();
-            let value = split.next().unwrap().trim();
+impl Lib {
+    fn parse(value: &Value) -> std::result::Result<Self, Invalid> {
+        const FIELDS: &[&str] = &["proc-macro", "crate-type", "name", "path"];
+        let Value::Table(table) = value else {
+            return Err(Invalid::value("lib", "lib must be a table"));
+        };
+        let mut lib = Self::default();
+        let mut proc_macro = false;
+        let mut crate_type = None;
+        for (field, value) in table {
+            match field.as_str() {
+                "proc-macro" => match value {
+                    Value::Boolean(value) => proc_macro = *value,
+                    _ => {
+                        return Err(Invalid::value(
+                            "lib.proc-macro",
+                            "lib.proc-macro must be a boolean",
+                        ))
+                    }
+                },
+                "crate-type" => {
+                    let Value::Array(values) = value else {
+                        return Err(Invalid::value(
+                            "lib.crate-type",
+                            "lib.crate-type must be an array of strings",
+                        ));
+                    };
+                    let mut types = Vec::new();
+                    for value in values {
+                        let ty = CrateType::from_str(expect_str("lib.crate-type", value)?)
+                            .map_err(|message| Invalid::value("lib.crate-type", message))?;
+                        match ty {
+                            CrateType::Bin => {
+                                return Err(Invalid::value(
+                                    "lib.crate-type",
+                                    "lib.crate-type can't contain bin",
+                                ));
+                            }
+                            ty if !types.contains(&ty) => types.push(ty),
+                            _ => {}
+                        }
+                    }
+                    if types.is_empty() {
+                        return Err(Invalid::value(
+                            "lib.crate-type",
+                            "lib.crate-type can't be empty",
+                        ));
+                    }
+                    crate_type = Some(types);
+                }
+                "name" => lib.name = Some(expect_str("lib.name", value)?.to_string()),
+                "path" => lib.path = Some(PathBuf::from(expect_str("lib.path", value)?)),
+                field => return Err(Invalid::unknown_field(field, "lib.", FIELDS)),
+            }
+        }
 
-            match field {
-                "name" => crate_name = Some(value.replace('"', "")),
-                "edition" => edition = Some(Edition::from_str(&value.replace('"', ""))?),
-                field => return Err(format!("Field {field} is unsupported").into()),
+        match (proc_macro, crate_type) {
+            (true, Some(types)) if types != [CrateType::ProcMacro] => {
+                return Err(Invalid::key(
+                    "lib.proc-macro",
+                    "lib.proc-macro conflicts with lib.crate-type",
+                ));
             }
+            (true, _) => lib.crate_type = vec![CrateType::ProcMacro],
+            (false, Some(types)) => lib.crate_type = types,
+            (false, None) => {}
+        }
+        if lib.crate_type.len() > 1 && lib.crate_type.contains(&CrateType::ProcMacro) {
+            return Err(Invalid::value(
+                "lib.crate-type",
+                "A proc-macro library can't also be another crate type",
+            ));
         }
+        Ok(lib)
+    }
+}
 
+impl Dependency {
+    fn parse(name: String, value: Value) -> std::result::Result<Self, Invalid> {
+        const FIELDS: &[&str] = &["path", "version"];
+        let key = format!("dependencies.{name}");
+        let table = match value {
+            Value::Table(table) => table,
+            Value::String(version) => {
+                VersionReq::from_str(&version).map_err(|message| Invalid::value(&key, message))?;
+                return Err(Invalid::value(
+                    &key,
+                    format!("Dependency {name} has no path, registry dependencies are unsupported"),
+                )
+                .help(format!(
+                    "use a path dependency like `{name} = {{ path = \"../{name}\" }}`"
+                )));
+            }
+            _ => {
+                return Err(Invalid::value(
+                    &key,
+                    format!("Dependency {name} must be a table with a path"),
+                ))
+            }
+        };
+        let mut path = None;
+        let mut version = None;
+        for (field, value) in table {
+            match field.as_str() {
+                "path" => path = Some(PathBuf::from(expect_str(&field, &value)?)),
+                "version" => {
+                    version = Some(
+                        VersionReq::from_str(expect_str(&field, &value)?)
+                            .map_err(|message| Invalid::value(format!("{key}.version"), message))?,
+                    )
+                }
+                field => return Err(Invalid::unknown_field(field, &format!("{key}."), FIELDS)),
+            }
+        }
         Ok(Self {
-            crate_name: crate_name.ok_or::<BoxError>("name is a required field".into())?,
-            edition: edition.ok_or::<BoxError>("edition is a required field".into())?,
+            path: path
+                .ok_or_else(|| Invalid::key(&key, format!("Dependency {name} needs a path")))?,
+            version,
+            name,
         })
     }
 }
+
+fn expect_str<'v>(field: &str, value: &'v Value) -> std::result::Result<&'v str, Invalid> {
+    value.as_str().ok_or_else(|| {
+        Invalid::value(
+            field,
+            format!("{field} must be a string, found {}", value.type_str()),
+        )
+    })
+}
+
+/// Why a manifest or config file was rejected, with the dotted key it's about
+/// so the error can point at it in the file
+struct Invalid {
+    message: String,
+    key: Option<String>,
+    /// Point at the value rather than the key
+    value: bool,
+    help: Option<String>,
+}
+
+impl Invalid {
+    fn key(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            key: Some(key.into()),
+            value: false,
+            help: None,
+        }
+    }
+
+    fn value(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            value: true,
+            ..Self::key(key, message)
+        }
+    }
+
+    /// A field that isn't one of `fields` in the table whose keys start with
+    /// `prefix`
+    fn unknown_field(field: &str, prefix: &str, fields: &[&str]) -> Self {
+        let invalid = Self::key(
+            format!("{prefix}{field}"),
+            format!("Field {prefix}{field} is unsupported"),
+        );
+        match did_you_mean(field, fields) {
+            Some(suggestion) => invalid.help(format!("did you mean `{suggestion}`?")),
+            None => invalid,
+        }
+    }
+
+    fn help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    fn into_error(self, path: &Path, contents: &str, spans: &toml::Spans) -> FreightError {
+        let span = self.key.and_then(|key| {
+            if self.value {
+                spans.value(&key)
+            } else {
+                spans.key(&key)
+            }
+        });
+        FreightError::manifest(path, contents, span, self.message, self.help)
+    }
+}
+
+impl From<&str> for Invalid {
+    fn from(message: &str) -> Self {
+        Self {
+            message: message.into(),
+            key: None,
+            value: false,
+            help: None,
+        }
+    }
+}
+
+/// Build settings from the layered config files, see [`Layers`]
+#[derive(Debug, Default)]
+pub struct Config {
+    pub build: BuildConfig,
+    pub cache: CacheConfig,
+    /// Settings for compiling for a target triple, from `[target.<triple>]`
+    pub target: BTreeMap<String, TargetConfig>,
+    /// Settings for a profile, from `[profile.<name>]`
+    pub profile: BTreeMap<String, ProfileConfig>,
+    /// Settings Freight accepts for Cargo's sake but ignores, to warn about
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct BuildConfig {
+    /// The targets to build for when `--target` isn't passed on the command line
+    pub target: Vec<String>,
+    /// Start compiling dependents as soon as the metadata of the libraries
+    /// they depend on is ready rather than waiting for them to be linked
+    pub pipelining: bool,
+    /// The rustc to use instead of the toolchain's
+    pub rustc: Option<PathBuf>,
+    /// The rustdoc to use instead of the toolchain's
+    pub rustdoc: Option<PathBuf>,
+    /// A program to run rustc through, like sccache
+    pub rustc_wrapper: Option<PathBuf>,
+    /// Where build output goes instead of `target` in the project root
+    pub target_dir: Option<PathBuf>,
+    /// How many rustc processes to run at once instead of one per CPU
+    pub jobs: Option<usize>,
+    /// Flags for every rustc invocation
+    pub rustflags: Option<Vec<String>>,
+    /// Flags for every rustdoc invocation
+    pub rustdocflags: Option<Vec<String>>,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            target: Vec::new(),
+            pipelining: true,
+            rustc: None,
+            rustdoc: None,
+            rustc_wrapper: None,
+            target_dir: None,
+            jobs: None,
+            rustflags: None,
+            rustdocflags: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TargetConfig {
+    /// Flags for rustc when compiling for the target, used instead of the
+    /// ones under `[build]`
+    pub rustflags: Option<Vec<String>>,
+    /// The linker rustc runs for the target
+    pub linker: Option<PathBuf>,
+    /// How to link the packages with `links = "<name>"`, from
+    /// `[target.<triple>.<name>]`, by name
+    pub links: BTreeMap<String, LinksOverride>,
+}
+
+/// What a `[target.<triple>.<name>]` section says to link for the package
+/// that links to the native library `<name>`, in place of what its build
+/// script would print
+#[derive(Debug, Default)]
+pub struct LinksOverride {
+    /// Native libraries to link, from `rustc-link-lib`, like `foo` or
+    /// `static=foo`
+    pub link_lib: Vec<String>,
+    /// Folders to search for native libraries, from `rustc-link-search`
+    pub link_search: Vec<PathBuf>,
+    /// `--cfg` values for the package, from `rustc-cfg`
+    pub cfg: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ProfileConfig {
+    /// Flags for rustc added after any others when compiling with the profile
+    pub rustflags: Option<Vec<String>>,
+}
+
+impl LinksOverride {
+    fn parse(
+        layers: &Layers,
+        key: &str,
+        table: &toml::Table,
+    ) -> std::result::Result<Self, Invalid> {
+        const FIELDS: &[&str] = &["rustc-link-lib", "rustc-link-search", "rustc-cfg"];
+        let mut links = Self::default();
+        for (field, value) in table {
+            let field_key = format!("{key}.{field}");
+            let values = match value {
+                Value::String(value) => vec![value.clone()],
+                Value::Array(_) => expect_flags(&field_key, value)?,
+                value => {
+                    return Err(Invalid::value(
+                        &field_key,
+                        format!(
+                            "`{field_key}` must be a string or an array, found {}",
+                            value.type_str()
+                        ),
+                    ))
+                }
+            };
+            match field.as_str() {
+                "rustc-link-lib" => links.link_lib = values,
+                "rustc-link-search" => {
+                    let base = layers.base(&field_key);
+                    links.link_search = values.iter().map(|path| base.join(path)).collect();
+                }
+                "rustc-cfg" => links.cfg = values,
+                field => return Err(Invalid::unknown_field(field, &format!("{key}."), FIELDS)),
+            }
+        }
+        Ok(links)
+    }
+}
+
+/// The compile cache shared between builds
+#[derive(Debug)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub dir: PathBuf,
+    /// How big the cache can get in bytes before old entries are evicted
+    pub max_size: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: freight_home().join("cache"),
+            max_size: 10 << 30,
+        }
+    }
+}
+
+/// Where Freight keeps files shared between projects, `$FREIGHT_HOME` or
+/// `~/.freight`
+pub fn freight_home() -> PathBuf {
+    match env::var_os("FREIGHT_HOME").filter(|home| !home.is_empty()) {
+        Some(home) => home.into(),
+        None => env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default()
+            .join(".freight"),
+    }
+}
+
+/// Every key Freight reads from config files, which can also be set with an
+/// environment variable like `FREIGHT_BUILD_JOBS` for `build.jobs`
+const KEYS: &[&str] = &[
+    "build.target",
+    "build.jobs",
+    "build.pipelining",
+    "build.rustc",
+    "build.rustdoc",
+    "build.rustc-wrapper",
+    "build.target-dir",
+    "build.rustflags",
+    "build.rustdocflags",
+    "cache.enabled",
+    "cache.dir",
+    "cache.max-size",
+];
+
+/// Where a config value was set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    File(PathBuf),
+    Env(String),
+}
+
+impl Origin {
+    /// What relative paths in a value are relative to: the folder holding
+    /// `.freight` for a config file and the current directory for the
+    /// environment
+    fn base(&self) -> PathBuf {
+        match self {
+            Self::File(path) => path
+                .parent()
+                .and_then(Path::parent)
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            Self::Env(_) => env::current_dir().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Env(name) => write!(f, "environment variable `{name}`"),
+        }
+    }
+}
+
+/// The config files that apply to a directory merged together. From lowest to
+/// highest precedence these are `$FREIGHT_HOME/config.toml`, then
+/// `.freight/config.toml` in each ancestor of the directory down to the
+/// directory itself, then environment variables. Tables are merged key by key
+/// and any other value replaces the one below it.
+#[derive(Debug, Default)]
+pub struct Layers {
+    table: toml::Table,
+    /// Where each value that isn't a table was set, by dotted key
+    origins: BTreeMap<String, Origin>,
+    /// The files that were read, to point errors at
+    files: Vec<(PathBuf, String, toml::Spans)>,
+}
+
+impl Layers {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut paths = vec![freight_home().join("config.toml")];
+        let mut ancestors = dir
+            .ancestors()
+            .map(|ancestor| ancestor.join(".freight").join("config.toml"))
+            .collect::<Vec<_>>();
+        ancestors.reverse();
+        paths.extend(ancestors);
+
+        let mut layers = Self::default();
+        for (index, path) in paths.iter().enumerate() {
+            // `~/.freight` is both the home and an ancestor of most projects
+            if !path.exists() || paths[index + 1..].contains(path) {
+                continue;
+            }
+            let contents = fs::read_to_string(path).map_err(FreightError::io(path))?;
+            let (table, spans) = toml::parse(&contents).map_err(|e| {
+                FreightError::manifest(path, &contents, Some(e.span), e.message, None)
+            })?;
+            let origin = Origin::File(path.clone());
+            for (key, value) in table {
+                layers.insert(&[key], value, &origin);
+            }
+            layers.files.push((path.clone(), contents, spans));
+        }
+        for key in KEYS {
+            let name = env_name(key);
+            let Ok(value) = env::var(&name) else {
+                continue;
+            };
+            // Kept as a string, keys that need another type convert it when
+            // they're read
+            let path = key.split('.').map(String::from).collect::<Vec<_>>();
+            layers.insert(&path, Value::String(value), &Origin::Env(name));
+        }
+        Ok(layers)
+    }
+
+    fn insert(&mut self, path: &[String], value: Value, origin: &Origin) {
+        let key = path.join(".");
+        if let Value::Table(table) = value {
+            for (child, value) in table {
+                let mut path = path.to_vec();
+                path.push(child);
+                self.insert(&path, value, origin);
+            }
+            return;
+        }
+        let mut table = &mut self.table;
+        for part in &path[..path.len() - 1] {
+            let entry = table
+                .entry(part.clone())
+                .or_insert_with(|| Value::Table(toml::Table::new()));
+            if !matches!(entry, Value::Table(_)) {
+                *entry = Value::Table(toml::Table::new());
+            }
+            let Value::Table(inner) = entry else {
+                unreachable!("the entry was just made a table");
+            };
+            table = inner;
+        }
+        table.insert(path[path.len() - 1].clone(), value);
+        let prefix = format!("{key}.");
+        self.origins.retain(|other, _| !other.starts_with(&prefix));
+        self.origins.insert(key, origin.clone());
+    }
+
+    /// The value at a dotted key like `build.jobs`
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let mut parts = key.split('.');
+        let mut value = self.table.get(parts.next()?)?;
+        for part in parts {
+            let Value::Table(table) = value else {
+                return None;
+            };
+            value = table.get(part)?;
+        }
+        Some(value)
+    }
+
+    /// Every value that isn't a table at or under `key`, with where it was set
+    pub fn values(&self, key: &str) -> Vec<(&str, &Origin)> {
+        let prefix = format!("{key}.");
+        self.origins
+            .iter()
+            .filter(|(other, _)| key.is_empty() || *other == key || other.starts_with(&prefix))
+            .map(|(other, origin)| (other.as_str(), origin))
+            .collect()
+    }
+
+    /// `value`, set at `key`, as an integer. Environment variables are
+    /// always strings, which are parsed here.
+    fn integer(&self, key: &str, value: &Value) -> Option<i64> {
+        match value {
+            Value::Integer(integer) => Some(*integer),
+            value => self.env_string(key, value)?.parse().ok(),
+        }
+    }
+
+    /// `value`, set at `key`, as a boolean, which the environment sets with
+    /// `true` or `false`
+    fn boolean(&self, key: &str, value: &Value) -> Option<bool> {
+        match value {
+            Value::Boolean(boolean) => Some(*boolean),
+            value => self.env_string(key, value)?.parse().ok(),
+        }
+    }
+
+    /// `value` if it's the string an environment variable set `key` to
+    fn env_string<'a>(&self, key: &str, value: &'a Value) -> Option<&'a str> {
+        match (value, self.origins.get(key)?) {
+            (Value::String(value), Origin::Env(_)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// What a relative path set at `key` is relative to
+    fn base(&self, key: &str) -> PathBuf {
+        self.origins
+            .get(key)
+            .map(Origin::base)
+            .unwrap_or_else(|| env::current_dir().unwrap_or_default())
+    }
+
+    /// Point an error at the file the value it's about came from
+    fn error(&self, invalid: Invalid) -> FreightError {
+        let origin = invalid.key.as_deref().and_then(|key| {
+            // A table that's the wrong type is a value in its own right
+            let mut key = key;
+            loop {
+                if let Some(origin) = self.origins.get(key) {
+                    return Some(origin);
+                }
+                key = key.rsplit_once('.')?.0;
+            }
+        });
+        match origin {
+            Some(Origin::File(path)) => match self.files.iter().find(|(file, _, _)| file == path) {
+                Some((path, contents, spans)) => invalid.into_error(path, contents, spans),
+                None => FreightError::InvalidArgument(invalid.message),
+            },
+            Some(Origin::Env(name)) => {
+                FreightError::InvalidArgument(format!("{name}: {}", invalid.message))
+            }
+            None => FreightError::InvalidArgument(invalid.message),
+        }
+    }
+}
+
+/// Flags set with an environment variable: `FREIGHT_ENCODED_<NAME>` separated
+/// by the ASCII unit separator so flags can contain spaces, or `<NAME>`
+/// separated by whitespace
+fn env_flags(name: &str) -> Option<Vec<String>> {
+    if let Ok(encoded) = env::var(format!("FREIGHT_ENCODED_{name}")) {
+        return Some(
+            encoded
+                .split('\x1f')
+                .filter(|flag| !flag.is_empty())
+                .map(String::from)
+                .collect(),
+        );
+    }
+    let flags = env::var(name).ok()?;
+    Some(flags.split_whitespace().map(String::from).collect())
+}
+
+/// Flags in a config file, either an array or a string separated by whitespace
+fn expect_flags(field: &str, value: &Value) -> std::result::Result<Vec<String>, Invalid> {
+    match value {
+        Value::String(flags) => Ok(flags.split_whitespace().map(String::from).collect()),
+        Value::Array(flags) => flags
+            .iter()
+            .map(|flag| {
+                flag.as_str().map(String::from).ok_or_else(|| {
+                    Invalid::value(field, format!("`{field}` must only contain strings"))
+                })
+            })
+            .collect(),
+        value => Err(Invalid::value(
+            field,
+            format!(
+                "`{field}` must be a string or an array, found {}",
+                value.type_str()
+            ),
+        )),
+    }
+}
+
+/// The environment variable overriding a config key
+fn env_name(key: &str) -> String {
+    format!("FREIGHT_{}", key.to_uppercase().replace(['.', '-'], "_"))
+}
+
+impl Config {
+    /// The config for the project at `root`
+    pub fn load(root: impl AsRef<Path>) -> Result<Self> {
+        let layers = Layers::load(root.as_ref())?;
+        Self::parse(&layers).map_err(|invalid| layers.error(invalid))
+    }
+
+    /// The flags to pass to rustc for units compiled for `triple` with
+    /// `profile`. Like Cargo the first of `FREIGHT_ENCODED_RUSTFLAGS`,
+    /// `RUSTFLAGS`, `[target.<triple>] rustflags` and `[build] rustflags` that
+    /// is set is used, then the profile's flags are added.
+    pub fn rustflags(&self, triple: &str, profile: &str) -> Vec<String> {
+        let mut flags = env_flags("RUSTFLAGS")
+            .or_else(|| self.target.get(triple)?.rustflags.clone())
+            .or_else(|| self.build.rustflags.clone())
+            .unwrap_or_default();
+        // The test and bench profiles inherit from dev and release
+        let inherits = match profile {
+            "test" => Some("dev"),
+            "bench" => Some("release"),
+            _ => None,
+        };
+        let profile_flags = [Some(profile), inherits]
+            .into_iter()
+            .flatten()
+            .find_map(|profile| self.profile.get(profile)?.rustflags.as_ref());
+        flags.extend(profile_flags.into_iter().flatten().cloned());
+        flags
+    }
+
+    /// The flags to pass to rustdoc, from `FREIGHT_ENCODED_RUSTDOCFLAGS`,
+    /// `RUSTDOCFLAGS` or `[build] rustdocflags`, whichever is set first
+    pub fn rustdocflags(&self) -> Vec<String> {
+        env_flags("RUSTDOCFLAGS")
+            .or_else(|| self.build.rustdocflags.clone())
+            .unwrap_or_default()
+    }
+
+    fn parse(layers: &Layers) -> std::result::Result<Self, Invalid> {
+        let table = &layers.table;
+        let mut config = Self::default();
+        if let Some(build) = table.get("build") {
+            let Value::Table(build) = build else {
+                return Err(Invalid::value("build", "`build` must be a table"));
+            };
+            match build.get("target") {
+                None => {}
+                Some(Value::String(target)) => config.build.target.push(target.clone()),
+                Some(Value::Array(targets)) => {
+                    for target in targets {
+                        config.build.target.push(
+                            target
+                                .as_str()
+                                .ok_or_else(|| {
+                                    Invalid::value(
+                                        "build.target",
+                                        "`build.target` must only contain strings",
+                                    )
+                                })?
+                                .to_string(),
+                        );
+                    }
+                }
+                Some(value) => {
+                    return Err(Invalid::value(
+                        "build.target",
+                        format!(
+                            "`build.target` must be a string or an array, found {}",
+                            value.type_str()
+                        ),
+                    ))
+                }
+            }
+            for (key, program) in [
+                ("rustc", &mut config.build.rustc),
+                ("rustdoc", &mut config.build.rustdoc),
+                ("rustc-wrapper", &mut config.build.rustc_wrapper),
+            ] {
+                if let Some(value) = build.get(key) {
+                    let value = expect_str(&format!("build.{key}"), value)?;
+                    // Paths are relative to the folder holding the config
+                    // file, anything else is looked up on the PATH
+                    *program = Some(if value.contains('/') {
+                        layers.base(&format!("build.{key}")).join(value)
+                    } else {
+                        value.into()
+                    });
+                }
+            }
+            if let Some(target_dir) = build.get("target-dir") {
+                config.build.target_dir = Some(
+                    layers
+                        .base("build.target-dir")
+                        .join(expect_str("build.target-dir", target_dir)?),
+                );
+            }
+            config.build.rustflags = build
+                .get("rustflags")
+                .map(|flags| expect_flags("build.rustflags", flags))
+                .transpose()?;
+            config.build.rustdocflags = build
+                .get("rustdocflags")
+                .map(|flags| expect_flags("build.rustdocflags", flags))
+                .transpose()?;
+            match build
+                .get("jobs")
+                .map(|jobs| (jobs, layers.integer("build.jobs", jobs)))
+            {
+                None => {}
+                Some((_, Some(jobs))) if jobs > 0 => config.build.jobs = Some(jobs as usize),
+                Some((_, Some(_))) => {
+                    return Err(Invalid::value(
+                        "build.jobs",
+                        "`build.jobs` must be at least 1",
+                    ))
+                }
+                Some((value, None)) => {
+                    return Err(Invalid::value(
+                        "build.jobs",
+                        format!(
+                            "`build.jobs` must be a positive integer, found {}",
+                            value.type_str()
+                        ),
+                    ))
+                }
+            }
+            match build
+                .get("pipelining")
+                .map(|pipelining| (pipelining, layers.boolean("build.pipelining", pipelining)))
+            {
+                None => {}
+                Some((_, Some(pipelining))) => config.build.pipelining = pipelining,
+                Some((value, None)) => {
+                    return Err(Invalid::value(
+                        "build.pipelining",
+                        format!(
+                            "`build.pipelining` must be a boolean, found {}",
+                            value.type_str()
+                        ),
+                    ))
+                }
+            }
+        }
+        for name in ["target", "profile"] {
+            let Some(tables) = table.get(name) else {
+                continue;
+            };
+            let Value::Table(tables) = tables else {
+                return Err(Invalid::value(name, format!("`{name}` must be a table")));
+            };
+            for (key, value) in tables {
+                let Value::Table(value) = value else {
+                    return Err(Invalid::value(
+                        format!("{name}.{key}"),
+                        format!("`{name}.{key}` must be a table"),
+                    ));
+                };
+                let flags = value
+                    .get("rustflags")
+                    .map(|flags| expect_flags(&format!("{name}.{key}.rustflags"), flags))
+                    .transpose()?;
+                if name == "profile" {
+                    config.profile.entry(key.clone()).or_default().rustflags = flags;
+                    continue;
+                }
+                let target = config.target.entry(key.clone()).or_default();
+                target.rustflags = flags;
+                for (field, value) in value {
+                    let field_key = format!("target.{key}.{field}");
+                    match (field.as_str(), value) {
+                        ("rustflags", _) => {}
+                        ("linker", value) => {
+                            let program = expect_str(&field_key, value)?;
+                            // Like the programs under `[build]`, paths are
+                            // relative to the folder holding the config file
+                            target.linker = Some(if program.contains('/') {
+                                layers.base(&field_key).join(program)
+                            } else {
+                                program.into()
+                            });
+                        }
+                        // Cargo accepts it too but has no use for it either
+                        ("ar", _) => config.warnings.push(format!(
+                            "`{field_key}` is ignored, rustc never runs an archiver"
+                        )),
+                        (_, Value::Table(links)) => {
+                            target.links.insert(
+                                field.clone(),
+                                LinksOverride::parse(layers, &field_key, links)?,
+                            );
+                        }
+                        _ => {
+                            return Err(Invalid::unknown_field(
+                                field,
+                                &format!("target.{key}."),
+                                &["rustflags", "linker"],
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(cache) = table.get("cache") {
+            let Value::Table(cache) = cache else {
+                return Err(Invalid::value("cache", "`cache` must be a table"));
+            };
+            match cache
+                .get("enabled")
+                .map(|enabled| (enabled, layers.boolean("cache.enabled", enabled)))
+            {
+                None => {}
+                Some((_, Some(enabled))) => config.cache.enabled = enabled,
+                Some((value, None)) => {
+                    return Err(Invalid::value(
+                        "cache.enabled",
+                        format!(
+                            "`cache.enabled` must be a boolean, found {}",
+                            value.type_str()
+                        ),
+                    ))
+                }
+            }
+            if let Some(dir) = cache.get("dir") {
+                config.cache.dir = layers.base("cache.dir").join(expect_str("cache.dir", dir)?);
+            }
+            match cache.get("max-size") {
+                None => {}
+                Some(Value::Integer(size)) if *size >= 0 => config.cache.max_size = *size as u64,
+                Some(Value::String(size)) => {
+                    config.cache.max_size = cache::parse_size(size).ok_or_else(|| {
+                        Invalid::value(
+                            "cache.max-size",
+                            format!("`{size}` is not a valid size"),
+                        )
+                        .help("sizes are a number of bytes with an optional unit like `500M` or `10GiB`")
+                    })?
+                }
+                Some(value) => {
+                    return Err(Invalid::value(
+                        "cache.max-size",
+                        format!(
+                            "`cache.max-size` must be a size like `10GiB`, found {}",
+                            value.type_str()
+                        ),
+                    ))
+                }
+            }
+        }
+        Ok(config)
+    }
+}
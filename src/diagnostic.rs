@@ -0,0 +1,149 @@
+//! Diagnostics rustc reports with `--error-format=json`
+use crate::json;
+use crate::json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+    Help,
+    FailureNote,
+    /// An internal compiler error
+    Ice,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    /// The error code like `E0308`, if the diagnostic has one
+    pub code: Option<String>,
+    /// The diagnostic as rustc would have printed it, with ANSI colors
+    pub rendered: Option<String>,
+    /// Where the diagnostic points to as the file, line and column
+    pub primary_span: Option<(String, u64, u64)>,
+    /// The whole message as rustc printed it
+    pub json: Value,
+}
+
+impl Diagnostic {
+    /// Parse a line rustc printed to stderr, which is `None` if the line isn't
+    /// a diagnostic, like an artifact notification
+    pub fn parse(line: &str) -> Option<Self> {
+        let message = json::parse(line).ok()?;
+        if message.get("$message_type").and_then(Value::as_str) != Some("diagnostic") {
+            return None;
+        }
+        let level = match message.get("level")?.as_str()? {
+            "error" => Level::Error,
+            "warning" => Level::Warning,
+            "note" => Level::Note,
+            "help" => Level::Help,
+            "failure-note" => Level::FailureNote,
+            "error: internal compiler error" => Level::Ice,
+            _ => return None,
+        };
+        let primary_span = match message.get("spans") {
+            Some(Value::Array(spans)) => spans
+                .iter()
+                .find(|span| span.get("is_primary") == Some(&Value::Boolean(true)))
+                .and_then(|span| {
+                    let file_name = span.get("file_name")?.as_str()?.to_string();
+                    let Value::Number(line) = span.get("line_start")? else {
+                        return None;
+                    };
+                    let Value::Number(column) = span.get("column_start")? else {
+                        return None;
+                    };
+                    Some((file_name, *line as u64, *column as u64))
+                }),
+            _ => None,
+        };
+        Some(Self {
+            level,
+            message: message.get("message")?.as_str()?.to_string(),
+            code: message
+                .get("code")
+                .and_then(|code| code.get("code"))
+                .and_then(Value::as_str)
+                .map(String::from),
+            rendered: message
+                .get("rendered")
+                .and_then(Value::as_str)
+                .map(String::from),
+            primary_span,
+            json: message,
+        })
+    }
+
+    /// The diagnostic on a single line, like rustc's `--error-format=short`
+    pub fn short(&self) -> String {
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Help => "help",
+            Level::FailureNote => "failure-note",
+            Level::Ice => "error: internal compiler error",
+        };
+        // Lint names are also codes but rustc only shows error codes
+        let code = match &self.code {
+            Some(code) if code.starts_with('E') => format!("[{code}]"),
+            _ => String::new(),
+        };
+        match &self.primary_span {
+            Some((file_name, line, column)) => {
+                format!(
+                    "{file_name}:{line}:{column}: {level}{code}: {}\n",
+                    self.message
+                )
+            }
+            None => format!("{level}{code}: {}\n", self.message),
+        }
+    }
+
+    /// rustc's own summary lines, which Freight replaces with one of its own
+    /// that also counts the warnings from fresh units
+    pub fn is_summary(&self) -> bool {
+        self.message.starts_with("aborting due to")
+            || self.message.ends_with("warning emitted")
+            || self.message.ends_with("warnings emitted")
+    }
+}
+
+#[test]
+fn parse_diagnostics() {
+    let diagnostic = Diagnostic::parse(
+        r#"{"$message_type":"diagnostic","message":"mismatched types","code":{"code":"E0308","explanation":null},"level":"error","spans":[{"file_name":"src/main.rs","line_start":3,"column_start":17,"is_primary":true}],"children":[],"rendered":"error[E0308]: mismatched types\n"}"#,
+    )
+    .unwrap();
+    assert_eq!(diagnostic.level, Level::Error);
+    assert_eq!(diagnostic.message, "mismatched types");
+    assert_eq!(diagnostic.code.as_deref(), Some("E0308"));
+    assert_eq!(
+        diagnostic.rendered.as_deref(),
+        Some("error[E0308]: mismatched types\n")
+    );
+    assert_eq!(
+        diagnostic.short(),
+        "src/main.rs:3:17: error[E0308]: mismatched types\n"
+    );
+    assert!(!diagnostic.is_summary());
+
+    let summary = Diagnostic::parse(
+        r#"{"$message_type":"diagnostic","message":"2 warnings emitted","code":null,"level":"warning","spans":[],"children":[],"rendered":"warning: 2 warnings emitted\n\n"}"#,
+    )
+    .unwrap();
+    assert_eq!(summary.code, None);
+    assert_eq!(summary.short(), "warning: 2 warnings emitted\n");
+    assert!(summary.is_summary());
+
+    assert_eq!(
+        Diagnostic::parse(
+            r#"{"$message_type":"artifact","artifact":"libfoo.rmeta","emit":"metadata"}"#
+        ),
+        None
+    );
+    assert_eq!(Diagnostic::parse("thread 'rustc' panicked"), None);
+}
@@ -0,0 +1,278 @@
+//! Everything that can go wrong while Freight runs
+use crate::semver::Version;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+/// A stretch of a line in a file. Lines and columns are counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    /// How many characters to underline, at least 1
+    pub len: usize,
+}
+
+/// A problem in a manifest or config file, rendered like a rustc diagnostic
+/// with the offending line underlined
+#[derive(Debug)]
+pub struct ManifestError {
+    pub path: PathBuf,
+    /// Where in the file the problem is, if it's known
+    pub span: Option<Span>,
+    /// The line `span` is on
+    pub source_line: Option<String>,
+    pub message: String,
+    /// A hint on how to fix it, like the field that was probably meant
+    pub help: Option<String>,
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = &self.message;
+        let gutter = self.span.map_or(0, |span| span.line.to_string().len());
+        match (self.span, &self.source_line) {
+            (Some(span), Some(line)) => {
+                // Keep tabs so the carets line up under the source
+                let indent = line
+                    .chars()
+                    .take(span.column - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect::<String>();
+                writeln!(f, "{message}")?;
+                writeln!(
+                    f,
+                    "{:gutter$}--> {}:{}:{}",
+                    "",
+                    self.path.display(),
+                    span.line,
+                    span.column
+                )?;
+                writeln!(f, "{:gutter$} |", "")?;
+                writeln!(f, "{} | {line}", span.line)?;
+                write!(f, "{:gutter$} | {indent}{}", "", "^".repeat(span.len))?;
+            }
+            _ => write!(f, "{}: {message}", self.path.display())?,
+        }
+        if let Some(help) = &self.help {
+            write!(f, "\n{:gutter$} = help: {help}", "")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum FreightError {
+    /// A `Freight.toml` or `.freight/config.toml` that isn't valid TOML or
+    /// has a value Freight doesn't accept
+    Manifest(Box<ManifestError>),
+    /// There's no `Freight.toml` in the directory or any of its parents
+    ManifestNotFound(PathBuf),
+    /// The package is missing what the command needs, like a binary to run
+    MissingTarget(String),
+    /// A target to compile for that rustc doesn't support
+    UnsupportedTarget(String),
+    /// The toolchain asked for isn't installed or is missing components
+    Toolchain(String),
+    /// The installed rustc is too old for something a package needs, like its
+    /// edition
+    RustcTooOld {
+        needed_by: String,
+        required: Version,
+        found: Version,
+    },
+    /// rustc or rustdoc failed on a unit, named like `` `foo` (lib) ``
+    CompilerFailed { unit: String, status: ExitStatus },
+    /// A test harness or the doc tests failed
+    TestFailed { name: String, status: ExitStatus },
+    Io {
+        /// The file or directory being worked with, if there was one
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// The path dependencies can't be put together into a graph
+    Resolver(String),
+    /// A program Freight relies on, like git or `rustc --print`, failed
+    Tool { program: String, message: String },
+    /// A command line argument Freight doesn't accept
+    InvalidArgument(String),
+}
+
+impl FreightError {
+    /// An error in a manifest or config file whose contents are `contents`
+    pub(crate) fn manifest(
+        path: impl Into<PathBuf>,
+        contents: &str,
+        span: Option<Span>,
+        message: impl Into<String>,
+        help: Option<String>,
+    ) -> Self {
+        Self::Manifest(Box::new(ManifestError {
+            path: path.into(),
+            span,
+            source_line: span
+                .and_then(|span| contents.lines().nth(span.line - 1))
+                .map(String::from),
+            message: message.into(),
+            help,
+        }))
+    }
+
+    /// For failed writes to stdout or stderr, which have no path to show
+    pub(crate) fn output(source: io::Error) -> Self {
+        Self::Io { path: None, source }
+    }
+
+    /// Attach the path being worked with to an I/O error, for use with
+    /// `map_err`
+    pub(crate) fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| Self::Io {
+            path: Some(path),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for FreightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Manifest(error) => write!(f, "{error}"),
+            Self::ManifestNotFound(dir) => write!(
+                f,
+                "Could not find Freight.toml in {} or any parent directory",
+                dir.display()
+            ),
+            Self::MissingTarget(message)
+            | Self::UnsupportedTarget(message)
+            | Self::Toolchain(message)
+            | Self::Resolver(message)
+            | Self::InvalidArgument(message) => write!(f, "{message}"),
+            Self::RustcTooOld {
+                needed_by,
+                required,
+                found,
+            } => write!(
+                f,
+                "{needed_by} requires rustc >= {} (you have {})",
+                short_version(required),
+                short_version(found)
+            ),
+            Self::CompilerFailed { unit, status } => {
+                write!(f, "Could not compile {unit}, the compiler {status}")
+            }
+            Self::TestFailed { name, status } => write!(f, "Test {name} failed, {status}"),
+            Self::Io {
+                path: Some(path),
+                source: _,
+            } => write!(f, "I/O error for {}", path.display()),
+            Self::Io { path: None, .. } => write!(f, "I/O error"),
+            Self::Tool { program, message } => write!(f, "{program} failed: {message}"),
+        }
+    }
+}
+
+impl Error for FreightError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A version without a zero patch number, like `1.85`
+fn short_version(version: &Version) -> String {
+    match version.patch {
+        0 => format!("{}.{}", version.major, version.minor),
+        patch => format!("{}.{}.{patch}", version.major, version.minor),
+    }
+}
+
+/// The candidate closest to `name` if it's close enough to be a likely typo
+pub(crate) fn did_you_mean<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max = (name.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[test]
+fn display_and_source() {
+    let error = FreightError::Io {
+        path: Some("target/debug".into()),
+        source: io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+    };
+    assert_eq!(error.to_string(), "I/O error for target/debug");
+    assert_eq!(error.source().unwrap().to_string(), "denied");
+
+    let error = FreightError::manifest(
+        "Freight.toml",
+        "name = \"freight\"\neditin = \"2021\"\n",
+        Some(Span {
+            line: 2,
+            column: 1,
+            len: 6,
+        }),
+        "Field editin is unsupported",
+        Some("did you mean `edition`?".into()),
+    );
+    assert_eq!(
+        error.to_string(),
+        "Field editin is unsupported\n \
+         --> Freight.toml:2:1\n  \
+         |\n\
+         2 | editin = \"2021\"\n  \
+         | ^^^^^^\n  \
+         = help: did you mean `edition`?"
+    );
+    assert!(error.source().is_none());
+
+    let error = FreightError::manifest("Freight.toml", "", None, "name is a required field", None);
+    assert_eq!(error.to_string(), "Freight.toml: name is a required field");
+
+    let error = FreightError::RustcTooOld {
+        needed_by: "edition 2024".into(),
+        required: Version::new(1, 85, 0),
+        found: Version::new(1, 80, 1),
+    };
+    assert_eq!(
+        error.to_string(),
+        "edition 2024 requires rustc >= 1.85 (you have 1.80.1)"
+    );
+
+    fn is_send_sync<T: Send + Sync>() {}
+    is_send_sync::<FreightError>();
+}
+
+#[test]
+fn suggestions() {
+    let fields = ["name", "edition", "version", "lib", "dependencies"];
+    assert_eq!(did_you_mean("editon", &fields), Some("edition"));
+    assert_eq!(did_you_mean("dependancies", &fields), Some("dependencies"));
+    assert_eq!(did_you_mean("nme", &fields), Some("name"));
+    assert_eq!(did_you_mean("bad_field", &fields), None);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+}
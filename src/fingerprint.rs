@@ -0,0 +1,143 @@
+//! Fingerprints decide if a unit is fresh, meaning it can skip compilation.
+//! A unit's fingerprint is a hash of the full rustc command line, the contents
+//! of every source file rustc read the last time it ran (from its dep-info
+//! file), the version of rustc and the fingerprints of the units it depends
+//! on. Fingerprints live in
+//! a `.fingerprint` folder next to the output they describe.
+use super::FreightError;
+use super::Result;
+use crate::rustc::RustcVersion;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+pub struct Fingerprint {
+    dir: PathBuf,
+    name: String,
+    /// A hash of the rustc the unit is compiled with, so that upgrading the
+    /// toolchain rebuilds everything
+    rustc: u64,
+}
+
+impl Fingerprint {
+    /// The fingerprint for the unit called `name` whose output is in `out_dir`
+    pub fn new(out_dir: &Path, name: impl Into<String>, rustc: &RustcVersion) -> Self {
+        let mut hasher = DefaultHasher::new();
+        rustc.hash(&mut hasher);
+        Self {
+            dir: out_dir.join(".fingerprint"),
+            name: name.into(),
+            rustc: hasher.finish(),
+        }
+    }
+
+    /// Make sure the folder the fingerprint and dep-info live in exists
+    pub fn create_dir(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir).map_err(FreightError::io(&self.dir))
+    }
+
+    /// Where rustc should write the unit's dep-info file
+    pub fn dep_info(&self) -> PathBuf {
+        self.dir.join(format!("{}.d", self.name))
+    }
+
+    /// Where the diagnostics from the last time the unit was compiled are
+    /// kept, so they can be shown again while the unit is fresh
+    pub fn output(&self) -> PathBuf {
+        self.dir.join(format!("{}.output", self.name))
+    }
+
+    fn hash_file(&self) -> PathBuf {
+        self.dir.join(&self.name)
+    }
+
+    /// Hash the inputs to the unit. This is `None` if rustc has never
+    /// successfully run for the unit since there's no dep-info to read.
+    pub fn compute(&self, command: &Command, deps: &[u64]) -> Result<Option<u64>> {
+        let Ok(dep_info) = fs::read_to_string(self.dep_info()) else {
+            return Ok(None);
+        };
+        let mut hasher = DefaultHasher::new();
+        self.rustc.hash(&mut hasher);
+        command.get_program().hash(&mut hasher);
+        for arg in command.get_args() {
+            arg.hash(&mut hasher);
+        }
+        deps.hash(&mut hasher);
+        for source in parse_dep_info(&dep_info) {
+            match fs::read(&source) {
+                Ok(contents) => {
+                    source.hash(&mut hasher);
+                    contents.hash(&mut hasher);
+                }
+                // A source file went away so the unit has to be rebuilt
+                Err(_) => return Ok(None),
+            }
+        }
+        Ok(Some(hasher.finish()))
+    }
+
+    /// The fingerprint recorded the last time the unit was compiled
+    pub fn stored(&self) -> Option<u64> {
+        u64::from_str_radix(fs::read_to_string(self.hash_file()).ok()?.trim(), 16).ok()
+    }
+
+    pub fn store(&self, fingerprint: u64) -> Result<()> {
+        let hash_file = self.hash_file();
+        fs::write(&hash_file, format!("{fingerprint:016x}")).map_err(FreightError::io(hash_file))
+    }
+}
+
+/// Every file listed as a dependency in a Makefile style dep-info file
+pub fn parse_dep_info(dep_info: &str) -> Vec<PathBuf> {
+    let mut sources = Vec::new();
+    for line in dep_info.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((_, deps)) = line.split_once(": ") else {
+            continue;
+        };
+        // Spaces in paths are escaped with a backslash
+        let mut current = String::new();
+        let mut chars = deps.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => current.extend(chars.next()),
+                ' ' => {
+                    if !current.is_empty() {
+                        sources.push(PathBuf::from(std::mem::take(&mut current)));
+                    }
+                }
+                c => current.push(c),
+            }
+        }
+        if !current.is_empty() {
+            sources.push(PathBuf::from(current));
+        }
+    }
+    sources.sort();
+    sources.dedup();
+    sources
+}
+
+#[test]
+fn dep_info_sources() {
+    let dep_info = "/out/libfoo.rlib: /src/lib.rs /src/my\\ mod.rs\n\
+                    \n\
+                    /src/lib.rs:\n\
+                    /src/my\\ mod.rs:\n\
+                    \n\
+                    # env-dep:FOO=bar\n";
+    assert_eq!(
+        parse_dep_info(dep_info),
+        vec![
+            PathBuf::from("/src/lib.rs"),
+            PathBuf::from("/src/my mod.rs")
+        ]
+    );
+}
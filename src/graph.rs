@@ -0,0 +1,463 @@
+//! The dependency graph: every package reachable from the root manifest by
+//! following path dependencies, and the units of compilation needed to build
+//! them.
+use super::FreightError;
+use super::Result;
+use crate::config::Manifest;
+use crate::rustc;
+use crate::rustc::CompileTarget;
+use crate::rustc::CrateType;
+use crate::rustc::RustcVersion;
+use crate::toolchain::Toolchain;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub struct Package {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+    /// Indices into [`Graph::packages`] of the packages this one depends on
+    pub deps: Vec<usize>,
+}
+
+impl Package {
+    pub fn lib_path(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.manifest.lib.path {
+            return Some(self.root.join(path));
+        }
+        let lib_rs = self.root.join("src").join("lib.rs");
+        lib_rs.exists().then_some(lib_rs)
+    }
+
+    pub fn main_path(&self) -> Option<PathBuf> {
+        let main_rs = self.root.join("src").join("main.rs");
+        main_rs.exists().then_some(main_rs)
+    }
+
+    /// The crate name of the library, which is what dependents refer to it by
+    pub fn lib_name(&self) -> &str {
+        self.manifest
+            .lib
+            .name
+            .as_deref()
+            .unwrap_or(&self.manifest.crate_name)
+    }
+
+    pub fn is_proc_macro(&self) -> bool {
+        self.manifest.lib.crate_type.contains(&CrateType::ProcMacro)
+    }
+
+    /// Whether the package has a library other Rust crates can link to. A
+    /// library that is only a `cdylib` or `staticlib` is only usable over the
+    /// C ABI.
+    pub fn has_rust_lib(&self) -> bool {
+        self.lib_path().is_some()
+            && self.manifest.lib.crate_type.iter().any(|crate_type| {
+                matches!(
+                    crate_type,
+                    CrateType::Lib | CrateType::RLib | CrateType::DyLib | CrateType::ProcMacro
+                )
+            })
+    }
+
+    /// The file name rustc gives this package's library on the host. Proc
+    /// macros and dylibs are dynamic libraries and the rest are rlibs.
+    pub fn lib_file_name(&self) -> String {
+        let crate_type = &self.manifest.lib.crate_type;
+        if crate_type.contains(&CrateType::Lib) || crate_type.contains(&CrateType::RLib) {
+            format!("lib{}.rlib", self.lib_name())
+        } else {
+            format!(
+                "{}{}{}",
+                env::consts::DLL_PREFIX,
+                self.lib_name(),
+                env::consts::DLL_SUFFIX
+            )
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Graph {
+    /// Every package in dependency order. A package always comes after the
+    /// packages it depends on, which means the root package is last.
+    pub packages: Vec<Package>,
+    /// The rustc and rustdoc the packages are built with
+    pub toolchain: Toolchain,
+    /// The version of the toolchain's rustc
+    pub rustc: RustcVersion,
+}
+
+impl Graph {
+    pub fn resolve(root: &Path) -> Result<Self> {
+        let root = root.canonicalize().map_err(FreightError::io(root))?;
+        let toolchain = Toolchain::resolve(&root)?;
+        let mut graph = Self {
+            packages: Vec::new(),
+            rustc: rustc::version(&toolchain)?,
+            toolchain,
+        };
+        graph.load(&root, &mut HashMap::new(), &mut Vec::new())?;
+
+        for package in &graph.packages {
+            let edition = package.manifest.edition;
+            if !edition.supported_by(&graph.rustc) {
+                return Err(FreightError::RustcTooOld {
+                    needed_by: format!(
+                        "edition {edition} of package {}",
+                        package.manifest.crate_name
+                    ),
+                    required: edition.min_rustc(),
+                    found: graph.rustc.version,
+                });
+            }
+        }
+        Ok(graph)
+    }
+
+    /// Make sure rustc is at least the `rust-version` of every package, which
+    /// `--ignore-rust-version` skips
+    pub fn check_rust_version(&self) -> Result<()> {
+        for package in &self.packages {
+            match &package.manifest.rust_version {
+                Some(required) if !self.rustc.at_least(required) => {
+                    return Err(FreightError::RustcTooOld {
+                        needed_by: format!("package {}", package.manifest.crate_name),
+                        required: required.clone(),
+                        found: self.rustc.version.clone(),
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn load(
+        &mut self,
+        root: &Path,
+        loaded: &mut HashMap<PathBuf, usize>,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<usize> {
+        if let Some(index) = loaded.get(root) {
+            return Ok(*index);
+        }
+        if stack.iter().any(|package| package == root) {
+            return Err(FreightError::Resolver(format!(
+                "Cyclic dependency detected on the package at {}",
+                root.display()
+            )));
+        }
+
+        let manifest = Manifest::parse_from_file(root.join("Freight.toml"))?;
+        stack.push(root.to_path_buf());
+        let mut deps = Vec::new();
+        for dep in &manifest.dependencies {
+            let dep_root = root.join(&dep.path).canonicalize().map_err(|e| {
+                FreightError::Resolver(format!(
+                    "Dependency {} of {} could not be found at {}: {e}",
+                    dep.name,
+                    manifest.crate_name,
+                    root.join(&dep.path).display()
+                ))
+            })?;
+            let index = self.load(&dep_root, loaded, stack)?;
+            let package = &self.packages[index];
+            if package.manifest.crate_name != dep.name {
+                return Err(FreightError::Resolver(format!(
+                    "Dependency {} of {} points to a package named {}",
+                    dep.name, manifest.crate_name, package.manifest.crate_name
+                )));
+            }
+            if let Some(req) = &dep.version {
+                match &package.manifest.version {
+                    Some(version) if req.matches(version) => {}
+                    Some(version) => {
+                        return Err(FreightError::Resolver(format!(
+                            "Dependency {} of {} requires version {req} but {} is at {version}",
+                            dep.name, manifest.crate_name, package.manifest.crate_name
+                        )))
+                    }
+                    None => {
+                        return Err(FreightError::Resolver(format!(
+                            "Dependency {} of {} requires version {req} but {} has no version",
+                            dep.name, manifest.crate_name, package.manifest.crate_name
+                        )))
+                    }
+                }
+            }
+            if !package.has_rust_lib() {
+                return Err(FreightError::Resolver(format!(
+                    "Dependency {} of {} has no Rust library to depend on",
+                    dep.name, manifest.crate_name
+                )));
+            }
+            deps.push(index);
+        }
+        stack.pop();
+
+        if self
+            .packages
+            .iter()
+            .any(|package| package.manifest.crate_name == manifest.crate_name)
+        {
+            return Err(FreightError::Resolver(format!(
+                "Two packages are named {}, package names must be unique",
+                manifest.crate_name
+            )));
+        }
+        if let Some(links) = &manifest.links {
+            if let Some(other) = self
+                .packages
+                .iter()
+                .find(|package| package.manifest.links.as_ref() == Some(links))
+            {
+                return Err(FreightError::Resolver(format!(
+                    "Packages {} and {} both link to the native library {links}, a native library can only be linked once",
+                    other.manifest.crate_name, manifest.crate_name
+                )));
+            }
+        }
+        self.packages.push(Package {
+            root: root.to_path_buf(),
+            manifest,
+            deps,
+        });
+        let index = self.packages.len() - 1;
+        loaded.insert(root.to_path_buf(), index);
+        Ok(index)
+    }
+
+    pub fn root(&self) -> &Package {
+        self.packages
+            .last()
+            .expect("The root package is always loaded")
+    }
+
+    /// Start collecting the units needed for a command. Units only emit
+    /// metadata if `check` is set, apart from the ones the compiler itself
+    /// needs like proc macros.
+    pub fn units(&self, check: bool) -> Units<'_> {
+        Units {
+            graph: self,
+            check,
+            units: Vec::new(),
+            libs: HashMap::new(),
+        }
+    }
+}
+
+/// What a unit is compiled for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CompileKind {
+    /// The machine running Freight. Proc macros are always built for the host.
+    Host,
+    Target(CompileTarget),
+}
+
+impl CompileKind {
+    pub fn target(&self) -> Option<&CompileTarget> {
+        match self {
+            Self::Host => None,
+            Self::Target(target) => Some(target),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnitTarget {
+    Lib,
+    Bin,
+    /// A test harness built with `rustc --test`
+    Test,
+}
+
+/// A single invocation of rustc
+#[derive(Debug)]
+pub struct Unit {
+    /// Index into [`Graph::packages`]
+    pub package: usize,
+    pub target: UnitTarget,
+    /// The crate root passed to rustc
+    pub src: PathBuf,
+    pub kind: CompileKind,
+    /// Only emit metadata, which is all dependents need to be checked
+    pub check: bool,
+    /// Indices of the units that need to be built before this one. These are
+    /// always libraries and always come earlier in the list of units.
+    pub deps: Vec<usize>,
+    /// Extra arguments for rustc from `freight rustc`, passed after all the
+    /// others
+    pub args: Vec<String>,
+}
+
+/// The units for a command in the order they need to be compiled. Every
+/// library is only compiled once for each kind.
+pub struct Units<'g> {
+    graph: &'g Graph,
+    check: bool,
+    units: Vec<Unit>,
+    libs: HashMap<(usize, CompileKind, bool), usize>,
+}
+
+impl Units<'_> {
+    /// Add the units to build the root package's library and binary
+    pub fn build(&mut self, kind: &CompileKind) {
+        self.root_lib(kind);
+        self.bin(kind);
+    }
+
+    /// Add the unit to build the root package's library, returning its index
+    /// if there is one
+    pub fn root_lib(&mut self, kind: &CompileKind) -> Option<usize> {
+        let root = self.graph.packages.len() - 1;
+        self.graph
+            .root()
+            .lib_path()
+            .map(|_| self.lib(root, kind, self.check))
+    }
+
+    /// Add the unit to build the root package's binary, returning its index
+    /// if there is one
+    pub fn bin(&mut self, kind: &CompileKind) -> Option<usize> {
+        let root = self.graph.packages.len() - 1;
+        let package = self.graph.root();
+        let main_rs = package.main_path()?;
+        // C ABI only libraries can't be used by the binary
+        let lib = self.root_lib(kind).filter(|_| package.has_rust_lib());
+        let deps = self
+            .dep_libs(root, kind, self.check)
+            .into_iter()
+            .chain(lib)
+            .collect();
+        Some(self.push(root, UnitTarget::Bin, main_rs, kind, self.check, deps))
+    }
+
+    /// Add the units to build the root package's test harnesses: one for the
+    /// library, the binary and every file in the `tests` folder
+    pub fn tests(&mut self, kind: &CompileKind) -> Result<()> {
+        let root = self.graph.packages.len() - 1;
+        let package = self.graph.root();
+        let check = self.check;
+        if let Some(lib_rs) = package.lib_path() {
+            let deps = self.dep_libs(root, kind, check);
+            self.push(root, UnitTarget::Test, lib_rs, kind, check, deps);
+        }
+        let lib = package.lib_path().map(|_| self.lib(root, kind, check));
+        let lib = lib.filter(|_| package.has_rust_lib());
+        if let Some(main_rs) = package.main_path() {
+            let deps = self
+                .dep_libs(root, kind, check)
+                .into_iter()
+                .chain(lib)
+                .collect();
+            self.push(root, UnitTarget::Test, main_rs, kind, check, deps);
+        }
+
+        if let Ok(items) = package.root.join("tests").read_dir() {
+            let mut tests = Vec::new();
+            for item in items {
+                let item = item.map_err(FreightError::io(package.root.join("tests")))?;
+                let path = item.path();
+                if item.file_type().map_err(FreightError::io(&path))?.is_file()
+                    && path.extension().map(|ext| ext == "rs").unwrap_or(false)
+                {
+                    tests.push(path);
+                }
+            }
+            tests.sort();
+            for test in tests {
+                self.test_file(test, kind);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the unit to build the harness for `tests/<name>.rs`, returning its
+    /// index if that file exists
+    pub fn test(&mut self, name: &str, kind: &CompileKind) -> Option<usize> {
+        let test = self
+            .graph
+            .root()
+            .root
+            .join("tests")
+            .join(format!("{name}.rs"));
+        test.is_file().then(|| self.test_file(test, kind))
+    }
+
+    fn test_file(&mut self, test: PathBuf, kind: &CompileKind) -> usize {
+        let root = self.graph.packages.len() - 1;
+        let package = self.graph.root();
+        let check = self.check;
+        let lib = self.root_lib(kind).filter(|_| package.has_rust_lib());
+        let deps = self
+            .dep_libs(root, kind, check)
+            .into_iter()
+            .chain(lib)
+            .collect();
+        self.push(root, UnitTarget::Test, test, kind, check, deps)
+    }
+
+    /// Add the units to build the libraries the root package depends on
+    pub fn deps(&mut self, kind: &CompileKind) {
+        self.dep_libs(self.graph.packages.len() - 1, kind, self.check);
+    }
+
+    pub fn finish(self) -> Vec<Unit> {
+        self.units
+    }
+
+    /// Add the library of `package` along with everything it depends on,
+    /// returning the index of the library unit
+    fn lib(&mut self, package: usize, kind: &CompileKind, check: bool) -> usize {
+        // The compiler runs proc macros so they and their dependencies are
+        // always fully built for the host
+        let (kind, check) = if self.graph.packages[package].is_proc_macro() {
+            (&CompileKind::Host, false)
+        } else {
+            (kind, check)
+        };
+        if let Some(index) = self.libs.get(&(package, kind.clone(), check)) {
+            return *index;
+        }
+        let deps = self.dep_libs(package, kind, check);
+        let src = self.graph.packages[package]
+            .lib_path()
+            .expect("Only packages with a library are depended on");
+        let index = self.push(package, UnitTarget::Lib, src, kind, check, deps);
+        self.libs.insert((package, kind.clone(), check), index);
+        index
+    }
+
+    /// The library units of every dependency of `package`
+    fn dep_libs(&mut self, package: usize, kind: &CompileKind, check: bool) -> Vec<usize> {
+        self.graph.packages[package]
+            .deps
+            .iter()
+            .map(|dep| self.lib(*dep, kind, check))
+            .collect()
+    }
+
+    fn push(
+        &mut self,
+        package: usize,
+        target: UnitTarget,
+        src: PathBuf,
+        kind: &CompileKind,
+        check: bool,
+        deps: Vec<usize>,
+    ) -> usize {
+        self.units.push(Unit {
+            package,
+            target,
+            src,
+            kind: kind.clone(),
+            check,
+            deps,
+            args: Vec::new(),
+        });
+        self.units.len() - 1
+    }
+}
@@ -0,0 +1,467 @@
+//! Runs rustc for several units at once. A unit starts as soon as what it
+//! needs from its dependencies is ready: the metadata of a library is enough to
+//! compile another library against, but anything that links needs every
+//! library below it fully built.
+use super::FreightError;
+use super::Result;
+use crate::cache::Cache;
+use crate::diagnostic::Diagnostic;
+use crate::diagnostic::Level;
+use crate::fingerprint::Fingerprint;
+use crate::json;
+use crate::logger::plural;
+use crate::logger::Logger;
+use crate::logger::Status;
+use crate::message::Artifact;
+use crate::timings::Timings;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process::ChildStderr;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+
+pub struct Job {
+    /// A rustc command that reports diagnostics and artifacts as JSON
+    pub command: Command,
+    /// The same rustc command only writing the dep-info, run to work out the
+    /// job's key in the compile cache
+    pub dep_info_command: Command,
+    pub fingerprint: Fingerprint,
+    /// Everything rustc writes, which all have to exist for the job to be fresh
+    pub outputs: Vec<PathBuf>,
+    /// Indices of the jobs this one depends on, which always come earlier, and
+    /// whether the dependency's metadata is enough for this job to start
+    pub deps: Vec<(usize, bool)>,
+    /// What to log when rustc is started, like `Compiling` and `lib foo`.
+    /// Fresh jobs are logged with the same message.
+    pub status: Option<(Status, String)>,
+    /// The unit as it's named in the warning and error summaries, like
+    /// `` `foo` (lib) ``
+    pub name: String,
+    pub artifact: Artifact,
+}
+
+enum State {
+    Pending,
+    Running {
+        metadata: bool,
+    },
+    /// rustc is done but the fingerprint can't be computed until the
+    /// dependencies are done too
+    Built,
+    Done {
+        fingerprint: u64,
+        fresh: bool,
+    },
+}
+
+enum Event {
+    Metadata(usize),
+    /// A line rustc printed that wasn't an artifact notification
+    Line(usize, String),
+    Finished(usize, io::Result<ExitStatus>),
+    /// The outputs were restored from the compile cache instead of running
+    /// rustc
+    Restored(usize),
+}
+
+/// What rustc printed for a job
+#[derive(Default)]
+struct Messages {
+    /// The diagnostics as the JSON rustc printed them
+    lines: Vec<String>,
+    warnings: usize,
+    errors: usize,
+}
+
+impl Messages {
+    fn handle(&mut self, logger: &mut Logger, job: &Job, line: &str) -> Result<()> {
+        let Some(diagnostic) = Diagnostic::parse(line) else {
+            return logger.compiler_output(line);
+        };
+        self.lines.push(line.to_string());
+        if diagnostic.is_summary() {
+            return Ok(());
+        }
+        match diagnostic.level {
+            Level::Warning => self.warnings += 1,
+            Level::Error | Level::Ice => self.errors += 1,
+            Level::Note | Level::Help | Level::FailureNote => {}
+        }
+        logger.diagnostic(&job.artifact, &diagnostic)
+    }
+}
+
+/// Run every job that isn't fresh with at most `parallelism` rustc processes
+/// going at once, restoring what it can from `cache` and recording when each
+/// job ran in `timings`. Once a job fails no more are started, but the ones
+/// already running are waited for before the first error is returned.
+pub fn run(
+    logger: &mut Logger,
+    mut jobs: Vec<Job>,
+    parallelism: usize,
+    cache: Option<&Cache>,
+    mut timings: Option<&mut Timings>,
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut states = jobs.iter().map(|_| State::Pending).collect::<Vec<_>>();
+    let mut messages = jobs.iter().map(|_| Messages::default()).collect::<Vec<_>>();
+    let mut running = 0;
+    let mut failed = None;
+    let mut hits = 0;
+    let mut misses = 0;
+    loop {
+        let mut progress = true;
+        while progress {
+            progress = finish_built(&jobs, &mut states)?;
+            for index in 0..jobs.len() {
+                if failed.is_some() {
+                    break;
+                }
+                if !matches!(states[index], State::Pending) || !is_ready(&jobs[index], &states) {
+                    continue;
+                }
+                if let Some(timings) = timings.as_deref_mut() {
+                    timings.queued(index);
+                }
+                if running >= parallelism {
+                    continue;
+                }
+                progress = true;
+                if let Some(fingerprint) = fresh(&jobs[index], &states)? {
+                    states[index] = State::Done {
+                        fingerprint,
+                        fresh: true,
+                    };
+                    if let Some(timings) = timings.as_deref_mut() {
+                        timings.fresh(index);
+                    }
+                    if let Some((_, message)) = &jobs[index].status {
+                        logger.status(Status::Fresh, message)?;
+                    }
+                    replay(logger, &jobs[index])?;
+                    logger.compiler_artifact(&jobs[index].artifact, true)?;
+                    continue;
+                }
+                if let Some((status, message)) = &jobs[index].status {
+                    logger.status(*status, message)?;
+                }
+                logger.command(&jobs[index].command)?;
+                if let Some(timings) = timings.as_deref_mut() {
+                    timings.started(index);
+                }
+                match cache {
+                    Some(cache) => {
+                        misses += 1;
+                        spawn_cached(index, &jobs[index], cache.clone(), tx.clone());
+                    }
+                    None => {
+                        // Stop starting units but wait for the running ones
+                        if let Err(error) = spawn(index, &mut jobs[index].command, tx.clone()) {
+                            failed.get_or_insert(error);
+                            break;
+                        }
+                    }
+                }
+                states[index] = State::Running { metadata: false };
+                running += 1;
+            }
+        }
+        if let Some(timings) = timings.as_deref_mut() {
+            let (ready, blocked): (Vec<_>, Vec<_>) = jobs
+                .iter()
+                .zip(&states)
+                .filter(|(_, state)| matches!(state, State::Pending))
+                .partition(|(job, _)| is_ready(job, &states));
+            timings.concurrency(running, ready.len(), blocked.len());
+        }
+        if running == 0 {
+            break;
+        }
+        let done = states
+            .iter()
+            .filter(|state| matches!(state, State::Built | State::Done { .. }))
+            .count();
+        let in_flight = jobs
+            .iter()
+            .zip(&states)
+            .filter(|(_, state)| matches!(state, State::Running { .. }))
+            .map(|(job, _)| job.artifact.name.as_str())
+            .collect::<Vec<_>>();
+        logger.progress(done, jobs.len(), &in_flight)?;
+
+        let (index, status) = match rx.recv().expect("rustc is still running for a job") {
+            Event::Metadata(index) => {
+                if let Some(timings) = timings.as_deref_mut() {
+                    timings.metadata(index);
+                }
+                states[index] = State::Running { metadata: true };
+                continue;
+            }
+            Event::Line(index, line) => {
+                messages[index].handle(logger, &jobs[index], &line)?;
+                continue;
+            }
+            Event::Finished(index, Ok(status)) => (index, Some(status)),
+            Event::Finished(index, Err(error)) => {
+                running -= 1;
+                let program = jobs[index].command.get_program();
+                failed.get_or_insert(FreightError::io(program)(error));
+                states[index] = State::Pending;
+                continue;
+            }
+            Event::Restored(index) => {
+                hits += 1;
+                misses -= 1;
+                (index, None)
+            }
+        };
+        running -= 1;
+        if let Some(timings) = timings.as_deref_mut() {
+            timings.finished(index, status.is_none());
+        }
+        let job = &jobs[index];
+        let Messages {
+            lines,
+            warnings,
+            errors,
+        } = &messages[index];
+        match status {
+            Some(status) if !status.success() => {
+                let mut message = format!("could not compile {}", job.name);
+                if *errors > 0 {
+                    message.push_str(&format!(" due to {}", plural(*errors, "previous error")));
+                }
+                if *warnings > 0 {
+                    message.push_str(&format!("; {} emitted", plural(*warnings, "warning")));
+                }
+                logger.status(Status::Error, message)?;
+                failed.get_or_insert(FreightError::CompilerFailed {
+                    unit: job.name.clone(),
+                    status,
+                });
+                states[index] = State::Pending;
+            }
+            _ => {
+                let output = job.fingerprint.output();
+                if let Err(error) = fs::write(&output, lines.join("\n")) {
+                    failed.get_or_insert(FreightError::io(output)(error));
+                    states[index] = State::Pending;
+                    continue;
+                }
+                if *warnings > 0 {
+                    logger.status(
+                        Status::Warning,
+                        format!("{} generated {}", job.name, plural(*warnings, "warning")),
+                    )?;
+                }
+                logger.compiler_artifact(&job.artifact, false)?;
+                states[index] = State::Built;
+            }
+        }
+    }
+
+    logger.clear_progress()?;
+    if let Some(cache) = cache {
+        cache.finish(hits, misses)?;
+    }
+    match failed {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn is_ready(job: &Job, states: &[State]) -> bool {
+    job.deps
+        .iter()
+        .all(|(dep, metadata_is_enough)| match states[*dep] {
+            State::Done { .. } => true,
+            State::Built | State::Running { metadata: true } => *metadata_is_enough,
+            State::Pending | State::Running { metadata: false } => false,
+        })
+}
+
+/// The fingerprint of the job if nothing it depends on was rebuilt and its own
+/// inputs haven't changed since it was last compiled
+fn fresh(job: &Job, states: &[State]) -> Result<Option<u64>> {
+    let mut dep_fingerprints = Vec::with_capacity(job.deps.len());
+    for (dep, _) in &job.deps {
+        match states[*dep] {
+            State::Done {
+                fingerprint,
+                fresh: true,
+            } => dep_fingerprints.push(fingerprint),
+            _ => return Ok(None),
+        }
+    }
+    let Some(current) = job.fingerprint.compute(&job.command, &dep_fingerprints)? else {
+        return Ok(None);
+    };
+    let outputs_exist = job.outputs.iter().all(|output| output.exists());
+    Ok((outputs_exist && job.fingerprint.stored() == Some(current)).then_some(current))
+}
+
+/// Show the warnings from the last time a fresh job was compiled again
+fn replay(logger: &mut Logger, job: &Job) -> Result<()> {
+    let Ok(output) = fs::read_to_string(job.fingerprint.output()) else {
+        return Ok(());
+    };
+    let mut messages = Messages::default();
+    for line in output.lines() {
+        messages.handle(logger, job, line)?;
+    }
+    if messages.warnings > 0 {
+        logger.status(
+            Status::Warning,
+            format!(
+                "{} generated {}",
+                job.name,
+                plural(messages.warnings, "warning")
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/// Record the fingerprints of the jobs rustc finished whose dependencies are
+/// all done, returning whether there were any
+fn finish_built(jobs: &[Job], states: &mut [State]) -> Result<bool> {
+    let mut finished = false;
+    for (index, job) in jobs.iter().enumerate() {
+        if !matches!(states[index], State::Built) {
+            continue;
+        }
+        let dep_fingerprints = job
+            .deps
+            .iter()
+            .map(|(dep, _)| match states[*dep] {
+                State::Done { fingerprint, .. } => Some(fingerprint),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some(dep_fingerprints) = dep_fingerprints else {
+            continue;
+        };
+        let fingerprint = job
+            .fingerprint
+            .compute(&job.command, &dep_fingerprints)?
+            .ok_or_else(|| FreightError::Io {
+                path: Some(job.fingerprint.dep_info()),
+                source: io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "rustc did not write a dep-info file",
+                ),
+            })?;
+        job.fingerprint.store(fingerprint)?;
+        states[index] = State::Done {
+            fingerprint,
+            fresh: false,
+        };
+        finished = true;
+    }
+    Ok(finished)
+}
+
+/// Start rustc and forward what it prints on stderr as events until it exits
+fn spawn(index: usize, command: &mut Command, tx: mpsc::Sender<Event>) -> Result<()> {
+    let mut child = command
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(FreightError::io(command.get_program()))?;
+    let stderr = child.stderr.take().expect("stderr is piped");
+    thread::spawn(move || {
+        forward(index, stderr, &tx);
+        let _ = tx.send(Event::Finished(index, child.wait()));
+    });
+    Ok(())
+}
+
+/// Restore the job's outputs from the cache, or run rustc like [`spawn`] and
+/// store what it produced if it succeeds
+fn spawn_cached(index: usize, job: &Job, cache: Cache, tx: mpsc::Sender<Event>) {
+    let mut command = copy(&job.command);
+    let mut dep_info_command = copy(&job.dep_info_command);
+    let dep_info = job.fingerprint.dep_info();
+    let outputs = job.outputs.clone();
+    thread::spawn(move || {
+        let key = cache.key(&command, &mut dep_info_command, &dep_info);
+        if let Some(lines) = key.as_ref().and_then(|key| cache.restore(key, &outputs)) {
+            for line in lines {
+                let _ = tx.send(Event::Line(index, line));
+            }
+            let _ = tx.send(Event::Restored(index));
+            return;
+        }
+        let mut child = match command.stderr(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = tx.send(Event::Finished(index, Err(e)));
+                return;
+            }
+        };
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let lines = forward(index, stderr, &tx);
+        let status = child.wait();
+        if let (Some(key), Ok(status)) = (&key, &status) {
+            // A cache that can't be written to only makes the next build
+            // slower, so it doesn't fail this one
+            if status.success() {
+                let _ = cache.store(key, &outputs, &lines);
+            }
+        }
+        let _ = tx.send(Event::Finished(index, status));
+    });
+}
+
+/// Send what rustc prints on stderr as events, returning the lines that
+/// weren't artifact notifications
+fn forward(index: usize, stderr: ChildStderr, tx: &mpsc::Sender<Event>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in BufReader::new(stderr).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let event = match json::parse(&line) {
+            Ok(message)
+                if message.get("$message_type").and_then(json::Value::as_str)
+                    == Some("artifact") =>
+            {
+                if message.get("emit").and_then(json::Value::as_str) != Some("metadata") {
+                    continue;
+                }
+                Event::Metadata(index)
+            }
+            _ => {
+                lines.push(line.clone());
+                Event::Line(index, line)
+            }
+        };
+        if tx.send(event).is_err() {
+            break;
+        }
+    }
+    lines
+}
+
+/// A new command with the same program, arguments, environment and working
+/// directory, since a [`Command`] can't be cloned
+fn copy(command: &Command) -> Command {
+    let mut copy = Command::new(command.get_program());
+    copy.args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => copy.env(key, value),
+            None => copy.env_remove(key),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        copy.current_dir(dir);
+    }
+    copy
+}
@@ -0,0 +1,345 @@
+//! A small JSON parser for reading the messages rustc prints with
+//! `--error-format=json`, and printer for the messages Freight prints with
+//! `--message-format=json`
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// The value of `key` if this is an object that has it
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(object) => object.get(key),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Boolean(b)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Self::Null)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Self::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<const N: usize> From<[(&str, Value); N]> for Value {
+    fn from(fields: [(&str, Value); N]) -> Self {
+        Self::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+}
+
+/// Values are printed as compact JSON on a single line
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Boolean(b) => write!(f, "{b}"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::String(s) => write_string(f, s),
+            Self::Array(array) => {
+                write!(f, "[")?;
+                for (i, value) in array.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Self::Object(object) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in object.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+type Result<T> = std::result::Result<T, String>;
+
+pub fn parse(input: &str) -> Result<Value> {
+    let mut parser = Parser { input, pos: 0 };
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != input.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('t') if self.eat("true") => Ok(Value::Boolean(true)),
+            Some('f') if self.eat("false") => Ok(Value::Boolean(false)),
+            Some('n') if self.eat("null") => Ok(Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut object = BTreeMap::new();
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(Value::Object(object));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            object.insert(key, self.value()?);
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(object)),
+                _ => return Err(self.error("expected `,` or `}` in object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut array = Vec::new();
+        self.skip_whitespace();
+        if self.eat("]") {
+            return Ok(Value::Array(array));
+        }
+        loop {
+            self.skip_whitespace();
+            array.push(self.value()?);
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(array)),
+                _ => return Err(self.error("expected `,` or `]` in array")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(string),
+                Some('\\') => match self.bump() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('/') => string.push('/'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => {
+                        let mut c = self.hex4()?;
+                        // Characters outside the basic multilingual plane are
+                        // written as a surrogate pair
+                        if (0xd800..0xdc00).contains(&c) && self.eat("\\u") {
+                            let low = self.hex4()?;
+                            c = 0x10000 + ((c - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                        }
+                        string.push(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        let c =
+            u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(c)
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.bump();
+        }
+        self.input[start..self.pos]
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(
+            self.peek(),
+            Some(' ') | Some('\t') | Some('\r') | Some('\n')
+        ) {
+            self.bump();
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.input[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.peek() == Some(c) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{c}`")))
+        }
+    }
+
+    fn error(&self, msg: impl std::fmt::Display) -> String {
+        format!("JSON parse error at byte {}: {msg}", self.pos)
+    }
+}
+
+#[test]
+fn parse_rustc_messages() -> Result<()> {
+    let artifact = parse(r#"{"artifact":"/tmp/libfoo.rmeta","emit":"metadata"}"#)?;
+    assert_eq!(
+        artifact.get("artifact").and_then(Value::as_str),
+        Some("/tmp/libfoo.rmeta")
+    );
+    assert_eq!(
+        artifact.get("emit").and_then(Value::as_str),
+        Some("metadata")
+    );
+
+    let diagnostic = parse(
+        r#"{"$message_type":"diagnostic","level":"warning","code":null,"spans":[{"line_start":1,"is_primary":true}],"rendered":"\u001b[1mwarning\u001b[0m: café \ud83d\ude80\n"}"#,
+    )?;
+    assert_eq!(diagnostic.get("code"), Some(&Value::Null));
+    let Some(Value::Array(spans)) = diagnostic.get("spans") else {
+        panic!("spans is not an array");
+    };
+    assert_eq!(spans[0].get("line_start"), Some(&Value::Number(1.0)));
+    assert_eq!(spans[0].get("is_primary"), Some(&Value::Boolean(true)));
+    assert_eq!(
+        diagnostic.get("rendered").and_then(Value::as_str),
+        Some("\u{1b}[1mwarning\u{1b}[0m: café 🚀\n")
+    );
+
+    assert!(parse("{\"a\":1").is_err());
+    assert!(parse("[1, 2] 3").is_err());
+    Ok(())
+}
+
+#[test]
+fn print_round_trips() -> Result<()> {
+    let value = Value::from([
+        ("reason", "compiler-artifact".into()),
+        ("fresh", false.into()),
+        ("executable", Value::Null),
+        (
+            "filenames",
+            vec!["/tmp/libfoo.rlib", "/tmp/\"quoted\"\n\u{1b}"].into(),
+        ),
+        ("count", Value::Number(3.0)),
+    ]);
+    let printed = value.to_string();
+    assert_eq!(
+        printed,
+        r#"{"count":3,"executable":null,"filenames":["/tmp/libfoo.rlib","/tmp/\"quoted\"\n\u001b"],"fresh":false,"reason":"compiler-artifact"}"#
+    );
+    assert_eq!(parse(&printed)?, value);
+    Ok(())
+}
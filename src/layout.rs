@@ -0,0 +1,89 @@
+//! Where build output goes. Everything lives under the target directory,
+//! which is `target` in the project root unless `--target-dir`, the
+//! `FREIGHT_TARGET_DIR` or `CARGO_TARGET_DIR` environment variables or the
+//! `[build] target-dir` config key say otherwise, in that order.
+use super::FreightError;
+use super::Result;
+use crate::config::Config;
+use crate::graph::CompileKind;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Layout {
+    target_dir: PathBuf,
+}
+
+impl Layout {
+    /// The layout for the project at `root`. A relative `target_dir` from the
+    /// command line or the environment is relative to the current directory.
+    pub fn new(root: &Path, target_dir: Option<&Path>) -> Result<Self> {
+        let from_env = ["FREIGHT_TARGET_DIR", "CARGO_TARGET_DIR"]
+            .iter()
+            .find_map(|name| env::var_os(name).filter(|dir| !dir.is_empty()))
+            .map(PathBuf::from);
+        let target_dir = match target_dir.map(Path::to_path_buf).or(from_env) {
+            Some(dir) => crate::current_dir()?.join(dir),
+            None => Config::load(root)?
+                .build
+                .target_dir
+                .unwrap_or_else(|| root.join("target")),
+        };
+        Ok(Self { target_dir })
+    }
+
+    pub fn target_dir(&self) -> &Path {
+        &self.target_dir
+    }
+
+    /// The folder output for `kind` goes in: `debug` for the host and
+    /// `<target>/debug` when cross compiling
+    pub fn dest(&self, kind: &CompileKind) -> PathBuf {
+        match kind {
+            CompileKind::Host => self.profile("debug"),
+            CompileKind::Target(compile_target) => {
+                self.target_dir.join(compile_target.name()).join("debug")
+            }
+        }
+    }
+
+    /// The folder a profile's host output goes in
+    pub fn profile(&self, profile: &str) -> PathBuf {
+        self.target_dir.join(profile)
+    }
+
+    /// Where `freight check` puts metadata for `kind`, kept apart from the
+    /// output of `freight build`
+    pub fn check(&self, kind: &CompileKind) -> PathBuf {
+        self.dest(kind).join("check")
+    }
+
+    /// Where test harnesses and the libraries they need are built
+    pub fn tests(&self) -> PathBuf {
+        self.profile("debug").join("tests")
+    }
+
+    /// Where the reports written with `--timings` go
+    pub fn timings(&self) -> PathBuf {
+        self.target_dir.join("freight-timings")
+    }
+
+    pub fn doc(&self) -> PathBuf {
+        self.target_dir.join("doc")
+    }
+}
+
+/// Copy `artifacts` into `artifact_dir` without any of the folders they were
+/// built in, for packaging them up
+pub fn copy_artifacts(artifact_dir: &Path, artifacts: &[PathBuf]) -> Result<()> {
+    fs::create_dir_all(artifact_dir).map_err(FreightError::io(artifact_dir))?;
+    for artifact in artifacts {
+        let Some(file_name) = artifact.file_name() else {
+            continue;
+        };
+        fs::copy(artifact, artifact_dir.join(file_name)).map_err(FreightError::io(artifact))?;
+    }
+    Ok(())
+}
@@ -1,308 +1,1367 @@
+mod cache;
 pub mod config;
+mod diagnostic;
+pub mod error;
+mod fingerprint;
+mod graph;
+mod job_queue;
+mod json;
+mod layout;
 mod logger;
+mod message;
+mod profile;
 pub mod rustc;
 pub mod rustdoc;
+pub mod semver;
+mod sha256;
+mod timings;
+mod toml;
+pub mod toolchain;
 
+use crate::cache::Cache;
+use crate::fingerprint::Fingerprint;
+use crate::graph::CompileKind;
+use crate::graph::Graph;
+use crate::graph::Package;
+use crate::graph::Unit;
+use crate::graph::UnitTarget;
+use crate::job_queue::Job;
+use crate::layout::Layout;
+use crate::message::Artifact;
+use crate::profile::Profile;
+use crate::rustc::CompileTarget;
 use crate::rustc::CrateType;
 use crate::rustc::Edition;
+use crate::rustc::Emit;
 use crate::rustc::Rustc;
+use crate::rustc::RustcBuilder;
 use crate::rustdoc::RustDoc;
+use crate::timings::Timings;
+use crate::toolchain::Toolchain;
+use config::Config;
+use config::Layers;
 use config::Manifest;
 use logger::Logger;
+use logger::Status;
 use std::env;
-use std::error::Error;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
+use std::str::FromStr;
+use std::thread;
 
-pub type Result<T> = std::result::Result<T, BoxError>;
-pub type BoxError = Box<dyn Error>;
+pub use error::FreightError;
+pub use logger::set_color;
+pub use logger::set_verbosity;
+pub use logger::ColorChoice;
+pub use logger::Verbosity;
 
-fn lib_compile(
+pub type Result<T> = std::result::Result<T, FreightError>;
+
+/// Compile every unit that isn't fresh, handing each one the libraries it
+/// depends on. `out_dir` decides where the output for each kind of unit goes.
+/// Where the root package's libraries end up is logged and returned if
+/// `report_artifacts` is set. A timing report is written if `timings` is set.
+fn compile_units(
     logger: &mut Logger,
-    manifest: &Manifest,
-    lib_path: &Path,
-    out_dir: &Path,
-) -> Result<()> {
-    logger.compiling_crate(&manifest.crate_name)?;
-    if Rustc::builder()
-        .edition(manifest.edition)
-        .crate_type(CrateType::Lib)
-        .crate_name(&manifest.crate_name)
-        .out_dir(out_dir)
-        .lib_dir(out_dir)
-        .done()
-        .run(lib_path.to_str().unwrap())?
-        .success()
-    {
-        Ok(())
-    } else {
-        Err("Compilation failed".into())
+    graph: &Graph,
+    units: &[Unit],
+    layout: &Layout,
+    out_dir: impl Fn(&CompileKind) -> PathBuf,
+    report_artifacts: bool,
+    timings: bool,
+) -> Result<Vec<PathBuf>> {
+    let config = Config::load(&graph.root().root)?;
+    for warning in &config.warnings {
+        logger.status(Status::Warning, warning)?;
     }
+    let pipelining = config.build.pipelining;
+    let cache = config.cache.enabled.then(|| {
+        Cache::new(&config.cache.dir, config.cache.max_size)
+            .rustc(&graph.rustc)
+            .paths(&graph.root().root, layout.target_dir())
+    });
+    let host_dir = out_dir(&CompileKind::Host);
+    let cross_compiling = units.iter().any(|unit| unit.kind.target().is_some());
+    let mut libs: Vec<Option<PathBuf>> = Vec::with_capacity(units.len());
+    let mut rmetas: Vec<Option<PathBuf>> = Vec::with_capacity(units.len());
+    // Native library search paths aren't stored in rlibs, so every unit gets
+    // the ones of everything it depends on for when it links
+    let mut link_search: Vec<Vec<PathBuf>> = Vec::with_capacity(units.len());
+    let mut jobs = Vec::with_capacity(units.len());
+    let mut artifacts = Vec::new();
+    for unit in units {
+        let package = &graph.packages[unit.package];
+        let unit_dir = out_dir(&unit.kind);
+        fs::create_dir_all(&unit_dir).map_err(FreightError::io(&unit_dir))?;
+        let links = unit_links(package, unit);
+        let mut deps = Vec::with_capacity(unit.deps.len());
+        let mut externs = Vec::with_capacity(unit.deps.len());
+        for dep in &unit.deps {
+            let dep_package = &graph.packages[units[*dep].package];
+            // Dependents that don't link only need the metadata of a library
+            // which is written before rustc starts on codegen
+            let lib = match &rmetas[*dep] {
+                Some(rmeta) if !links => rmeta,
+                _ => libs[*dep]
+                    .as_ref()
+                    .expect("Units only depend on Rust libraries"),
+            };
+            deps.push((*dep, !links && rmetas[*dep].is_some()));
+            externs.push(format!("{}={}", dep_package.lib_name(), lib.display()));
+        }
+
+        let pipelined = pipelining && unit_is_pipelined(package, unit);
+        let triple = match unit.kind.target() {
+            Some(target) => target.name(),
+            None => &graph.rustc.host,
+        };
+        let profile = Profile::for_target(unit.target);
+        // Like Cargo, units built for the host while cross compiling don't get
+        // the flags meant for the target
+        let flags = if cross_compiling && unit.kind == CompileKind::Host {
+            Vec::new()
+        } else {
+            config.rustflags(triple, profile.name)
+        };
+        let mut builder = Rustc::builder().flags(flags).flags(unit.args.iter());
+        for r#extern in externs {
+            builder = builder.externs(r#extern);
+        }
+        let target_config = config.target.get(triple);
+        if let Some(linker) = target_config.and_then(|target| target.linker.as_ref()) {
+            builder = builder.linker(linker);
+        }
+        let mut search = Vec::new();
+        let links = package
+            .manifest
+            .links
+            .as_ref()
+            .and_then(|links| target_config?.links.get(links));
+        if let Some(links) = links {
+            for lib in &links.link_lib {
+                builder = builder.link_lib(lib);
+            }
+            for cfg in &links.cfg {
+                builder = builder.cfg(cfg);
+            }
+            search.extend(links.link_search.iter().cloned());
+        }
+        for dep in &unit.deps {
+            for dir in &link_search[*dep] {
+                if !search.contains(dir) {
+                    search.push(dir.clone());
+                }
+            }
+        }
+        for dir in &search {
+            builder = builder.link_search(dir);
+        }
+        link_search.push(search);
+        let (job, unit_artifacts) =
+            compile_unit(graph, unit, builder, &unit_dir, &host_dir, deps, pipelined)?;
+        jobs.push(job);
+
+        // Prefer linking to the rlib if there is one, otherwise a Rust library
+        // is a dylib, a proc macro or only metadata and is the only artifact
+        let rlib = unit_artifacts.iter().find(|artifact| {
+            artifact
+                .extension()
+                .map(|ext| ext == "rlib")
+                .unwrap_or(false)
+        });
+        libs.push(match unit.target {
+            UnitTarget::Lib if package.has_rust_lib() => rlib.or(unit_artifacts.first()).cloned(),
+            UnitTarget::Lib | UnitTarget::Bin | UnitTarget::Test => None,
+        });
+        rmetas.push(pipelined.then(|| unit_dir.join(format!("lib{}.rmeta", package.lib_name()))));
+        if report_artifacts && unit.package == graph.packages.len() - 1 {
+            artifacts.extend(
+                unit_artifacts
+                    .into_iter()
+                    .map(|artifact| (package.root.clone(), artifact)),
+            );
+        }
+    }
+
+    let parallelism = config.build.jobs.unwrap_or_else(|| {
+        thread::available_parallelism()
+            .map(|parallelism| parallelism.get())
+            .unwrap_or(1)
+    });
+    let mut report = timings.then(|| Timings::new(&jobs, parallelism));
+    let result = job_queue::run(logger, jobs, parallelism, cache.as_ref(), report.as_mut());
+    logger.build_finished(result.is_ok())?;
+    // The report is written even if the build failed, to see how far it got
+    if let Some(report) = report {
+        let html = report.write(&layout.timings())?;
+        let current_dir = current_dir()?;
+        logger.status(
+            Status::Timing,
+            format!(
+                "report saved to {}",
+                html.strip_prefix(&current_dir).unwrap_or(&html).display()
+            ),
+        )?;
+    }
+    result?;
+    for (root, artifact) in &artifacts {
+        logger.status(
+            Status::Artifact,
+            artifact.strip_prefix(root).unwrap_or(artifact).display(),
+        )?;
+    }
+    Ok(artifacts
+        .into_iter()
+        .map(|(_, artifact)| artifact)
+        .collect())
 }
 
-fn bin_compile(
-    logger: &mut Logger,
-    manifest: &Manifest,
-    bin_path: &Path,
+/// Whether rustc links the unit, which needs every library it depends on to
+/// be fully built rather than only their metadata
+fn unit_links(package: &Package, unit: &Unit) -> bool {
+    match unit.target {
+        UnitTarget::Bin | UnitTarget::Test => !unit.check,
+        UnitTarget::Lib => {
+            !unit.check
+                && package
+                    .manifest
+                    .lib
+                    .crate_type
+                    .iter()
+                    .any(|crate_type| !matches!(crate_type, CrateType::Lib | CrateType::RLib))
+        }
+    }
+}
+
+/// Whether the unit writes its metadata separately from, and before, the rlib
+/// so that dependents can start compiling early. Only libraries that are fully
+/// built and used by other Rust crates through an rlib can be.
+fn unit_is_pipelined(package: &Package, unit: &Unit) -> bool {
+    let crate_type = &package.manifest.lib.crate_type;
+    unit.target == UnitTarget::Lib
+        && !unit.check
+        && !package.is_proc_macro()
+        && (crate_type.contains(&CrateType::Lib) || crate_type.contains(&CrateType::RLib))
+}
+
+/// Set up the rustc invocation for a unit, returning it as a job for the queue
+/// along with the paths of what it produces. `builder` already has the unit's
+/// dependencies and flags.
+fn compile_unit(
+    graph: &Graph,
+    unit: &Unit,
+    builder: RustcBuilder,
     out_dir: &Path,
-    externs: &[&str],
-) -> Result<()> {
-    logger.compiling_bin(&manifest.crate_name)?;
-    let mut builder = Rustc::builder()
+    host_dir: &Path,
+    deps: Vec<(usize, bool)>,
+    pipelined: bool,
+) -> Result<(Job, Vec<PathBuf>)> {
+    let package = &graph.packages[unit.package];
+    let manifest = &package.manifest;
+    let mut builder = builder
+        .toolchain(&graph.toolchain)
         .edition(manifest.edition)
-        .crate_type(CrateType::Bin)
-        .crate_name(&manifest.crate_name)
         .out_dir(out_dir)
         .lib_dir(out_dir);
 
-    for ex in externs {
-        builder = builder.externs(*ex);
+    // Cross compiled units can still depend on proc macros built for the host
+    if out_dir != host_dir {
+        builder = builder.lib_dir(host_dir);
     }
 
-    if builder.done().run(bin_path.to_str().unwrap())?.success() {
-        Ok(())
+    let is_lib_src = package.lib_path().as_ref() == Some(&unit.src);
+    let test_name = if is_lib_src {
+        "lib"
     } else {
-        Err("Compilation failed".into())
+        unit.src.file_stem().unwrap().to_str().unwrap()
+    };
+    let unit_name = match unit.target {
+        UnitTarget::Lib => format!("lib-{}", package.lib_name()),
+        UnitTarget::Bin => format!("bin-{}", manifest.crate_name),
+        UnitTarget::Test => format!("test-{}-{test_name}", manifest.crate_name),
+    };
+    let unit_name = if unit.check {
+        format!("{unit_name}-check")
+    } else {
+        unit_name
+    };
+
+    match unit.target {
+        UnitTarget::Lib => {
+            for crate_type in &manifest.lib.crate_type {
+                builder = builder.crate_type(*crate_type);
+            }
+            builder = builder.crate_name(package.lib_name());
+        }
+        UnitTarget::Bin => {
+            builder = builder
+                .crate_type(CrateType::Bin)
+                .crate_name(&manifest.crate_name);
+        }
+        UnitTarget::Test => {
+            builder = builder
+                .crate_type(CrateType::Bin)
+                .crate_name(format!("test_{}_{test_name}", &manifest.crate_name))
+                .test(true);
+        }
     }
-}
 
-fn test_compile(
-    manifest: &Manifest,
-    bin_path: &Path,
-    out_dir: &Path,
-    externs: &[&str],
-) -> Result<()> {
-    let mut builder = Rustc::builder()
-        .edition(manifest.edition)
-        .crate_type(CrateType::Bin)
-        .crate_name(format!(
-            "test_{}_{}",
-            &manifest.crate_name,
-            bin_path.file_stem().unwrap().to_str().unwrap()
-        ))
-        .out_dir(out_dir)
-        .lib_dir(out_dir)
-        .test(true);
+    // Proc macros, and the unit tests for them, need the compiler's proc_macro
+    // crate which isn't in the extern prelude by default
+    if package.is_proc_macro() && is_lib_src {
+        builder = builder.externs("proc_macro");
+    }
 
-    for ex in externs {
-        builder = builder.externs(*ex);
+    if let Some(target) = unit.kind.target() {
+        builder = builder.target(target.rustc_target());
     }
 
-    if builder.done().run(bin_path.to_str().unwrap())?.success() {
-        Ok(())
+    let fingerprint = Fingerprint::new(out_dir, &unit_name, &graph.rustc);
+    fingerprint.create_dir()?;
+    builder = builder.emit_to(Emit::DepInfo, fingerprint.dep_info());
+    // Binaries and tests get their metadata renamed so it can't overwrite the
+    // metadata of a library with the same crate name
+    let rmeta = match unit.target {
+        UnitTarget::Lib => out_dir.join(format!("lib{}.rmeta", package.lib_name())),
+        UnitTarget::Bin | UnitTarget::Test => out_dir.join(format!("{unit_name}.rmeta")),
+    };
+    builder = match (unit.check, unit.target) {
+        (false, _) if pipelined => builder.emit(Emit::Metadata).emit(Emit::Link),
+        (false, _) => builder.emit(Emit::Link),
+        (true, UnitTarget::Lib) => builder.emit(Emit::Metadata),
+        (true, _) => builder.emit_to(Emit::Metadata, &rmeta),
+    };
+
+    let rustc = builder.json(true).done();
+    let src = unit.src.to_str().unwrap();
+    let artifacts = if unit.check {
+        vec![rmeta.clone()]
     } else {
-        Err("Compilation failed".into())
+        rustc.file_names(src)?
+    };
+    let mut outputs = artifacts.clone();
+    if pipelined {
+        outputs.push(rmeta);
     }
+
+    let crate_name = &manifest.crate_name;
+    let name = match unit.target {
+        UnitTarget::Lib => format!("`{crate_name}` (lib)"),
+        UnitTarget::Bin => format!("`{crate_name}` (bin \"{crate_name}\")"),
+        UnitTarget::Test if is_lib_src => format!("`{crate_name}` (lib test)"),
+        UnitTarget::Test if package.main_path().as_ref() == Some(&unit.src) => {
+            format!("`{crate_name}` (bin \"{crate_name}\" test)")
+        }
+        UnitTarget::Test => format!("`{crate_name}` (test \"{test_name}\")"),
+    };
+    let status = if unit.check {
+        Status::Checking
+    } else {
+        Status::Compiling
+    };
+    let status = match unit.target {
+        UnitTarget::Lib => Some((status, format!("lib {crate_name}"))),
+        UnitTarget::Bin => Some((status, format!("bin {crate_name}"))),
+        UnitTarget::Test => None,
+    };
+    let is_main_src = package.main_path().as_ref() == Some(&unit.src);
+    let lib_crate_types = manifest
+        .lib
+        .crate_type
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    // Test harnesses are described by the target they test, like Cargo does
+    let (kind, crate_types, target_name) = match unit.target {
+        UnitTarget::Lib | UnitTarget::Test if is_lib_src => {
+            (lib_crate_types.clone(), lib_crate_types, package.lib_name())
+        }
+        UnitTarget::Test if !is_main_src => (vec!["test".into()], vec!["bin".into()], test_name),
+        UnitTarget::Lib | UnitTarget::Bin | UnitTarget::Test => {
+            (vec!["bin".into()], vec!["bin".into()], crate_name.as_str())
+        }
+    };
+    let artifact = Artifact {
+        package_id: format!("path+file://{}#{crate_name}", package.root.display()),
+        manifest_path: package.root.join("Freight.toml"),
+        kind,
+        crate_types,
+        name: target_name.to_string(),
+        src_path: unit.src.clone(),
+        edition: manifest.edition.to_string(),
+        test: unit.target == UnitTarget::Test,
+        profile: Profile::for_target(unit.target),
+        filenames: outputs.clone(),
+        executable: match unit.target {
+            UnitTarget::Bin | UnitTarget::Test if !unit.check => artifacts.first().cloned(),
+            UnitTarget::Lib | UnitTarget::Bin | UnitTarget::Test => None,
+        },
+    };
+    let job = Job {
+        command: rustc.command(src),
+        dep_info_command: rustc.dep_info_command(src, &fingerprint.dep_info()),
+        fingerprint,
+        outputs,
+        deps,
+        status,
+        name,
+        artifact,
+    };
+    Ok((job, artifacts))
+}
+
+/// Create a project in a new directory at `path`
+pub fn new(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    fs::create_dir_all(path).map_err(FreightError::io(path))?;
+    init(path)
 }
 
 pub fn init(path: impl AsRef<Path>) -> Result<()> {
     let path = path.as_ref();
-    fs::write(path.join(".gitignore"), b"/target")?;
+    let gitignore = path.join(".gitignore");
+    fs::write(&gitignore, b"/target").map_err(FreightError::io(gitignore))?;
     let src = path.join("src");
-    fs::create_dir_all(&src)?;
+    fs::create_dir_all(&src).map_err(FreightError::io(&src))?;
+    let main_rs = src.join("main.rs");
     fs::write(
-        src.join("main.rs"),
+        &main_rs,
         b"fn main() {\n    println!(\"Hello, World!\");\n}\n",
-    )?;
+    )
+    .map_err(FreightError::io(main_rs))?;
     if !Command::new("git")
         .arg("init")
         .arg(path)
-        .output()?
+        .output()
+        .map_err(FreightError::io("git"))?
         .status
         .success()
     {
-        return Err("git failed to initialize a repository".into());
+        return Err(FreightError::Tool {
+            program: "git".into(),
+            message: "could not initialize a repository".into(),
+        });
     }
     let crate_name = path.file_name().unwrap().to_str().unwrap();
-    let toml = format!("name = \"{crate_name}\"\nedition = \"2021\"\n");
-    fs::write(path.join("Freight.toml"), toml.as_bytes())?;
+    let edition = Edition::latest(&rustc::version(&Toolchain::resolve(path)?)?);
+    let toml = format!("name = \"{crate_name}\"\nedition = \"{edition}\"\n");
+    let manifest = path.join("Freight.toml");
+    fs::write(&manifest, toml.as_bytes()).map_err(FreightError::io(manifest))?;
 
     Ok(())
 }
 
-pub fn run(run_args: Vec<String>) -> Result<()> {
+/// Build the root package's binary and run it with `run_args`, returning the
+/// code to exit with
+pub fn run(options: &BuildOptions, run_args: Vec<String>) -> Result<i32> {
     let root_dir = root_dir()?;
     let main_rs = root_dir.join("src").join("main.rs");
     if main_rs.exists() {
-        build()?;
+        let toolchain = Toolchain::resolve(&root_dir)?;
+        let layout = Layout::new(&root_dir, options.target_dir.as_deref())?;
+        let target_debug = match compile_kinds(&root_dir, &toolchain, &options.targets)?.as_slice()
+        {
+            [kind] => layout.dest(kind),
+            _ => {
+                return Err(FreightError::InvalidArgument(
+                    "`freight run` can only run a binary for one target".into(),
+                ))
+            }
+        };
+        build(options)?;
         let manifest = Manifest::parse_from_file(root_dir.join("Freight.toml"))?;
-        let target = root_dir.join("target");
-        let target_debug = target.join("debug");
         let path = target_debug.join(manifest.crate_name);
-        Command::new(path).args(run_args).spawn()?.wait()?;
-
-        Ok(())
+        // Show the path the way it could be typed from where freight was run
+        let current_dir = current_dir()?;
+        let shown = path.strip_prefix(&current_dir).unwrap_or(&path);
+        let words = [shown.display().to_string()]
+            .into_iter()
+            .chain(run_args.iter().cloned())
+            .map(|word| logger::quote(&word))
+            .collect::<Vec<_>>();
+        Logger::new(options.message_format).status(Status::Running, words.join(" "))?;
+        let mut command = Command::new(&path);
+        command.args(run_args);
+        run_binary(command, &path)
     } else {
-        Err("Cannot call `freight run` if there is no binary to run".into())
+        Err(FreightError::MissingTarget(
+            "Cannot call `freight run` if there is no binary to run".into(),
+        ))
+    }
+}
+
+/// Replace Freight with the binary, so that it gets signals like Ctrl-C
+/// directly and whatever runs `freight run` sees it exit
+#[cfg(unix)]
+fn run_binary(mut command: Command, path: &Path) -> Result<i32> {
+    use std::os::unix::process::CommandExt;
+    // exec only returns if it failed
+    Err(FreightError::io(path)(command.exec()))
+}
+
+/// Run the binary and wait for it, passing on its exit code. Ctrl-C goes to
+/// every process on the console so it reaches the binary without any help.
+#[cfg(not(unix))]
+fn run_binary(mut command: Command, path: &Path) -> Result<i32> {
+    let status = command
+        .spawn()
+        .map_err(FreightError::io(path))?
+        .wait()
+        .map_err(FreightError::io(path))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// How diagnostics and the results of a build are reported, set with
+/// `--message-format`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Diagnostics the way rustc prints them
+    #[default]
+    Human,
+    /// Diagnostics on a single line each
+    Short,
+    /// A JSON message on stdout for every diagnostic and artifact, matching
+    /// the messages Cargo prints
+    Json,
+    /// Like `Json` but the rendered diagnostics are on a single line
+    JsonDiagnosticShort,
+}
+
+impl FromStr for MessageFormat {
+    type Err = FreightError;
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "human" => Ok(Self::Human),
+            "short" => Ok(Self::Short),
+            "json" => Ok(Self::Json),
+            "json-diagnostic-short" => Ok(Self::JsonDiagnosticShort),
+            format => Err(FreightError::InvalidArgument(format!(
+                "Unsupported message format {format}, expected one of human, short, json or json-diagnostic-short"
+            ))),
+        }
     }
 }
 
-pub fn build() -> Result<()> {
-    let mut logger = Logger::new();
+/// Options for `freight build`
+#[derive(Debug, Default)]
+pub struct BuildOptions {
+    /// Targets passed with `--target`. When empty the `[build] target` key in
+    /// `.freight/config.toml` is used, and if that's not set then the host.
+    pub targets: Vec<String>,
+    pub message_format: MessageFormat,
+    /// Build even if rustc is older than a package's `rust-version`
+    pub ignore_rust_version: bool,
+    /// Where build output goes instead of the configured target directory
+    pub target_dir: Option<PathBuf>,
+    /// A folder to copy the root package's binaries and libraries into
+    pub artifact_dir: Option<PathBuf>,
+    /// Write a report of when each unit was compiled to `freight-timings` in
+    /// the target directory
+    pub timings: bool,
+}
+
+pub fn build(options: &BuildOptions) -> Result<()> {
+    let mut logger = Logger::new(options.message_format);
     let root_dir = root_dir()?;
-    let manifest = Manifest::parse_from_file(root_dir.join("Freight.toml"))?;
+    let graph = Graph::resolve(&root_dir)?;
+    if !options.ignore_rust_version {
+        graph.check_rust_version()?;
+    }
+    let mut units = graph.units(false);
+    for kind in compile_kinds(&root_dir, &graph.toolchain, &options.targets)? {
+        units.build(&kind);
+    }
+    let units = units.finish();
+    if units.is_empty() {
+        return Err(FreightError::MissingTarget(
+            "There is nothing to compile".into(),
+        ));
+    }
 
-    let lib_rs = root_dir.join("src").join("lib.rs");
-    let main_rs = root_dir.join("src").join("main.rs");
-    let target = root_dir.join("target");
-    let target_debug = target.join("debug");
-    fs::create_dir_all(&target_debug)?;
-
-    match (lib_rs.exists(), main_rs.exists()) {
-        (true, true) => {
-            lib_compile(&mut logger, &manifest, &lib_rs, &target_debug)?;
-            bin_compile(
-                &mut logger,
-                &manifest,
-                &main_rs,
-                &target_debug,
-                &[&manifest.crate_name],
-            )?;
+    let layout = Layout::new(&root_dir, options.target_dir.as_deref())?;
+    let artifacts = compile_units(
+        &mut logger,
+        &graph,
+        &units,
+        &layout,
+        |kind| layout.dest(kind),
+        true,
+        options.timings,
+    )?;
+    if let Some(artifact_dir) = &options.artifact_dir {
+        layout::copy_artifacts(artifact_dir, &artifacts)?;
+    }
+    logger.status(Status::Finished, Profile::DEV.finished(logger.elapsed()))?;
+    Ok(())
+}
+
+/// Options for `freight check`
+#[derive(Debug, Default)]
+pub struct CheckOptions {
+    /// Targets passed with `--target`, the same as for [`BuildOptions`]
+    pub targets: Vec<String>,
+    /// Check the test harnesses instead of the library and binary
+    pub tests: bool,
+    /// Check the library, binary and test harnesses
+    pub all_targets: bool,
+    pub message_format: MessageFormat,
+    /// Check even if rustc is older than a package's `rust-version`
+    pub ignore_rust_version: bool,
+    /// Where output goes instead of the configured target directory
+    pub target_dir: Option<PathBuf>,
+    /// Write a timing report, the same as for [`BuildOptions`]
+    pub timings: bool,
+}
+
+/// Type check the package without doing any codegen. Only metadata is emitted
+/// and it goes into a `check` folder so that it can't be confused with the
+/// output of `freight build`.
+pub fn check(options: &CheckOptions) -> Result<()> {
+    let mut logger = Logger::new(options.message_format);
+    let root_dir = root_dir()?;
+    let graph = Graph::resolve(&root_dir)?;
+    if !options.ignore_rust_version {
+        graph.check_rust_version()?;
+    }
+    let mut units = graph.units(true);
+    for kind in compile_kinds(&root_dir, &graph.toolchain, &options.targets)? {
+        if options.all_targets || !options.tests {
+            units.build(&kind);
         }
-        (true, false) => {
-            lib_compile(&mut logger, &manifest, &lib_rs, &target_debug)?;
+        if options.all_targets || options.tests {
+            units.tests(&kind)?;
         }
-        (false, true) => {
-            bin_compile(&mut logger, &manifest, &main_rs, &target_debug, &[])?;
+    }
+    let units = units.finish();
+    if units.is_empty() {
+        return Err(FreightError::MissingTarget(
+            "There is nothing to check".into(),
+        ));
+    }
+
+    let layout = Layout::new(&root_dir, options.target_dir.as_deref())?;
+    compile_units(
+        &mut logger,
+        &graph,
+        &units,
+        &layout,
+        |kind| layout.check(kind),
+        false,
+        options.timings,
+    )?;
+    logger.status(Status::Finished, Profile::DEV.finished(logger.elapsed()))?;
+    Ok(())
+}
+
+/// Which of the root package's crates `freight rustc` passes its arguments to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CrateFilter {
+    Lib,
+    Bin(String),
+    /// The harness for `tests/<name>.rs`
+    Test(String),
+}
+
+/// Options for `freight rustc`
+#[derive(Debug, Default)]
+pub struct RustcOptions {
+    /// The crate to pass `args` to. It can be left out if the package only
+    /// has a library or only has a binary.
+    pub filter: Option<CrateFilter>,
+    /// Extra arguments for rustc that only the selected crate gets
+    pub args: Vec<String>,
+    /// Print this compiler information, like `cfg`, instead of compiling
+    pub print: Option<String>,
+    /// Crate types to build the library as instead of the ones in
+    /// `Freight.toml`
+    pub crate_types: Vec<String>,
+    /// Targets passed with `--target`, the same as for [`BuildOptions`]
+    pub targets: Vec<String>,
+    pub message_format: MessageFormat,
+    /// Compile even if rustc is older than a package's `rust-version`
+    pub ignore_rust_version: bool,
+    /// Where build output goes instead of the configured target directory
+    pub target_dir: Option<PathBuf>,
+}
+
+/// Build one of the root package's crates with extra arguments for rustc.
+/// Its dependencies are built as usual and don't get the arguments.
+pub fn rustc(options: &RustcOptions) -> Result<()> {
+    let mut logger = Logger::new(options.message_format);
+    let root_dir = root_dir()?;
+    let mut graph = Graph::resolve(&root_dir)?;
+    if !options.ignore_rust_version {
+        graph.check_rust_version()?;
+    }
+    let kind = match compile_kinds(&root_dir, &graph.toolchain, &options.targets)?.as_slice() {
+        [kind] => kind.clone(),
+        _ => {
+            return Err(FreightError::InvalidArgument(
+                "`freight rustc` can only compile for one target".into(),
+            ))
         }
-        (false, false) => return Err("There is nothing to compile".into()),
+    };
+
+    if let Some(print) = &options.print {
+        let triple = match kind.target() {
+            Some(target) => target.name(),
+            None => &graph.rustc.host,
+        };
+        let status = Command::new(&graph.toolchain.rustc)
+            .arg("--print")
+            .arg(print)
+            .arg("--color")
+            .arg(if logger.color() { "always" } else { "never" })
+            .args(
+                kind.target()
+                    .iter()
+                    .flat_map(|target| ["--target", target.rustc_target()]),
+            )
+            .args(Config::load(&root_dir)?.rustflags(triple, "dev"))
+            .args(&options.args)
+            .status()
+            .map_err(FreightError::io(&graph.toolchain.rustc))?;
+        return if status.success() {
+            Ok(())
+        } else {
+            Err(FreightError::Tool {
+                program: "rustc".into(),
+                message: format!("could not print {print}"),
+            })
+        };
     }
 
+    let package = graph.root();
+    let crate_name = &package.manifest.crate_name;
+    let filter = match &options.filter {
+        Some(CrateFilter::Bin(name)) if name != crate_name => {
+            let mut message = format!("no binary named `{name}`");
+            if let Some(suggestion) = error::did_you_mean(name, &[crate_name]) {
+                message.push_str(&format!(", did you mean `{suggestion}`?"));
+            }
+            return Err(FreightError::MissingTarget(message));
+        }
+        Some(filter) => filter.clone(),
+        None => match (package.lib_path(), package.main_path()) {
+            (Some(_), None) => CrateFilter::Lib,
+            (None, Some(_)) => CrateFilter::Bin(crate_name.clone()),
+            _ => {
+                return Err(FreightError::InvalidArgument(
+                    "extra arguments for rustc can only go to one crate, pick one with `--lib`, `--bin NAME` or `--test NAME`"
+                        .into(),
+                ))
+            }
+        },
+    };
+    if !options.crate_types.is_empty() {
+        if filter != CrateFilter::Lib {
+            return Err(FreightError::InvalidArgument(
+                "`--crate-type` can only be used with the library".into(),
+            ));
+        }
+        let crate_types = options
+            .crate_types
+            .iter()
+            .map(|crate_type| crate_type.parse())
+            .collect::<std::result::Result<Vec<CrateType>, String>>()
+            .map_err(FreightError::InvalidArgument)?;
+        let root = graph.packages.len() - 1;
+        graph.packages[root].manifest.lib.crate_type = crate_types;
+    }
+
+    let mut units = graph.units(false);
+    let index = match &filter {
+        CrateFilter::Lib => units
+            .root_lib(&kind)
+            .ok_or_else(|| FreightError::MissingTarget("There is no library to compile".into()))?,
+        CrateFilter::Bin(_) => units
+            .bin(&kind)
+            .ok_or_else(|| FreightError::MissingTarget("There is no binary to compile".into()))?,
+        CrateFilter::Test(name) => units.test(name, &kind).ok_or_else(|| {
+            FreightError::MissingTarget(format!("There is no test named `{name}`"))
+        })?,
+    };
+    let mut units = units.finish();
+    units[index].args = options.args.clone();
+
+    let layout = Layout::new(&root_dir, options.target_dir.as_deref())?;
+    compile_units(
+        &mut logger,
+        &graph,
+        &units,
+        &layout,
+        |kind| layout.dest(kind),
+        true,
+        false,
+    )?;
+    logger.status(
+        Status::Finished,
+        Profile::for_target(units[index].target).finished(logger.elapsed()),
+    )?;
     Ok(())
 }
 
-pub fn doc(_open: bool) -> Result<()> {
-    let root = root_dir()?;
-    let manifest = Manifest::parse_from_file(root.join("Freight.toml"))?;
-    let target = root.join("target");
-    let lib_path = target.join("debug");
-    let doc_path = target.join("doc");
-    if RustDoc::new(
-        manifest.edition,
-        manifest.crate_name,
-        lib_path,
-        Some(doc_path),
-    )
-    // TODO Fix no main.rs
-    .doc(root.join("src").join("lib.rs"))?
-    .success()
-    {
-        Ok(())
+/// Validate the targets we were asked to build for, falling back to the ones
+/// in `.freight/config.toml` and then the host if none were given on the
+/// command line
+fn compile_kinds(
+    root_dir: &Path,
+    toolchain: &Toolchain,
+    targets: &[String],
+) -> Result<Vec<CompileKind>> {
+    let targets = if targets.is_empty() {
+        Config::load(root_dir)?
+            .build
+            .target
+            .iter()
+            .map(|target| {
+                // Spec files in the config are relative to the project root
+                if target.ends_with(".json") {
+                    CompileTarget::new(root_dir.join(target).to_str().unwrap(), toolchain)
+                } else {
+                    CompileTarget::new(target, toolchain)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?
     } else {
-        Err("Failed to document items".into())
+        targets
+            .iter()
+            .map(|target| CompileTarget::new(target, toolchain))
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    if targets.is_empty() {
+        Ok(vec![CompileKind::Host])
+    } else {
+        Ok(targets.into_iter().map(CompileKind::Target).collect())
     }
 }
 
-pub fn build_tests() -> Result<()> {
-    let mut logger = Logger::new();
-    let root_dir = root_dir()?;
-    let manifest = Manifest::parse_from_file(root_dir.join("Freight.toml"))?;
+#[derive(Default)]
+pub struct CleanOptions {
+    /// Only remove the output of the release profile
+    pub release: bool,
+    /// Only remove the output of this profile
+    pub profile: Option<String>,
+    /// Only remove the output for these targets
+    pub targets: Vec<String>,
+    /// Only remove the artifacts and fingerprints of these packages
+    pub packages: Vec<String>,
+    /// Only remove the docs
+    pub doc: bool,
+    /// List what would be removed without removing anything
+    pub dry_run: bool,
+    /// Remove the compile cache entries no target directory uses anymore
+    /// instead of any build output
+    pub gc: bool,
+    /// The target directory to clean instead of the configured one
+    pub target_dir: Option<PathBuf>,
+}
 
-    let lib_rs = root_dir.join("src").join("lib.rs");
-    let main_rs = root_dir.join("src").join("main.rs");
-    let target = root_dir.join("target");
-    let target_tests = target.join("debug").join("tests");
-    fs::create_dir_all(&target_tests)?;
-
-    match (lib_rs.exists(), main_rs.exists()) {
-        (true, true) => {
-            test_compile(&manifest, &lib_rs, &target_tests, &[])?;
-            lib_compile(&mut logger, &manifest, &lib_rs, &target_tests)?;
-            test_compile(&manifest, &main_rs, &target_tests, &[&manifest.crate_name])?;
+/// Remove build output. With no options that's the whole target directory,
+/// otherwise only what the options pick out.
+pub fn clean(options: &CleanOptions) -> Result<()> {
+    let mut logger = Logger::new(MessageFormat::Human);
+    let root = root_dir()?;
+    let layout = Layout::new(&root, options.target_dir.as_deref())?;
+    let remove = if options.gc {
+        let config = Config::load(&root)?;
+        Cache::new(&config.cache.dir, config.cache.max_size).unreferenced()?
+    } else {
+        let profiles = match (options.release, &options.profile) {
+            (true, Some(_)) => {
+                return Err(FreightError::InvalidArgument(
+                    "--release and --profile can't be used together".into(),
+                ))
+            }
+            (true, None) => vec!["release"],
+            (false, Some(profile)) => vec![profile_dir(profile)],
+            (false, None) => Vec::new(),
+        };
+        let mut bases = options
+            .targets
+            .iter()
+            .map(|triple| {
+                // Spec files build into a folder named after the file
+                let name = match triple.strip_suffix(".json") {
+                    Some(spec) => Path::new(spec).file_name().unwrap_or_default(),
+                    None => triple.as_ref(),
+                };
+                layout.target_dir().join(name)
+            })
+            .collect::<Vec<_>>();
+        if bases.is_empty() {
+            bases.push(layout.target_dir().to_path_buf());
+        }
+
+        let packages = if options.packages.is_empty() {
+            Vec::new()
+        } else {
+            let graph = Graph::resolve(&root)?;
+            let mut packages = Vec::new();
+            for name in &options.packages {
+                let Some(package) = graph
+                    .packages
+                    .iter()
+                    .find(|package| &package.manifest.crate_name == name)
+                else {
+                    let names = graph
+                        .packages
+                        .iter()
+                        .map(|package| package.manifest.crate_name.as_str())
+                        .collect::<Vec<_>>();
+                    let mut message = format!("package `{name}` is not in the dependency graph");
+                    if let Some(suggestion) = error::did_you_mean(name, &names) {
+                        message.push_str(&format!(", did you mean `{suggestion}`?"));
+                    }
+                    return Err(FreightError::InvalidArgument(message));
+                };
+                packages.push((package.lib_name().to_string(), package_stems(package)));
+            }
+            packages
+        };
+
+        let mut remove = Vec::new();
+        if options.doc {
+            let doc = layout.doc();
+            if packages.is_empty() {
+                remove.push(doc);
+            } else {
+                for (lib_name, _) in &packages {
+                    remove.push(doc.join(lib_name));
+                    remove.push(doc.join("src").join(lib_name));
+                }
+            }
+        } else if packages.is_empty() {
+            for base in &bases {
+                if profiles.is_empty() {
+                    remove.push(base.clone());
+                }
+                remove.extend(profiles.iter().map(|profile| base.join(profile)));
+            }
+        } else {
+            let profiles = if profiles.is_empty() {
+                vec!["debug", "release"]
+            } else {
+                profiles
+            };
+            let stems = packages
+                .iter()
+                .flat_map(|(_, stems)| stems)
+                .collect::<Vec<_>>();
+            for base in &bases {
+                for profile in &profiles {
+                    for (file, _) in files(&base.join(profile))? {
+                        let stem = file.file_stem().and_then(|stem| stem.to_str());
+                        if stem.is_some_and(|stem| stems.iter().any(|s| *s == stem)) {
+                            remove.push(file);
+                        }
+                    }
+                }
+            }
+        }
+        remove
+    };
+
+    let mut count = 0;
+    let mut size = 0;
+    for path in &remove {
+        for (file, file_size) in files(path)? {
+            if options.dry_run {
+                logger.status(
+                    Status::Removing,
+                    file.strip_prefix(&root).unwrap_or(&file).display(),
+                )?;
+            }
+            count += 1;
+            size += file_size;
         }
-        (true, false) => {
-            test_compile(&manifest, &lib_rs, &target_tests, &[])?;
+        if options.dry_run || !path.exists() {
+            continue;
         }
-        (false, true) => {
-            test_compile(&manifest, &main_rs, &target_tests, &[])?;
+        if path.is_dir() {
+            fs::remove_dir_all(path).map_err(FreightError::io(path))?;
+        } else {
+            fs::remove_file(path).map_err(FreightError::io(path))?;
         }
-        (false, false) => return Err("There is nothing to compile".into()),
     }
+    let status = if options.dry_run {
+        Status::Summary
+    } else {
+        Status::Removed
+    };
+    logger.status(
+        status,
+        format!(
+            "{}, {} total",
+            logger::plural(count, "file"),
+            cache::format_size(size)
+        ),
+    )?;
+    if options.dry_run {
+        logger.status(Status::Warning, "no files deleted due to --dry-run")?;
+    }
+    Ok(())
+}
+
+/// The folder a profile's output goes in, which for the built in profiles
+/// isn't their name
+fn profile_dir(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        profile => profile,
+    }
+}
 
-    if let Ok(items) = root_dir.join("tests").read_dir() {
-        for item in items {
-            let item = item?;
-            let is_file = item.file_type()?.is_file();
-            let path = item.path();
-            if is_file && path.extension().map(|ext| ext == "rs").unwrap_or(false) {
-                test_compile(&manifest, &path, &target_tests, &[&manifest.crate_name])?;
+/// The file stems of every artifact, fingerprint and dep-info file the units
+/// of a package produce
+fn package_stems(package: &Package) -> Vec<String> {
+    let crate_name = &package.manifest.crate_name;
+    let lib_name = package.lib_name();
+    let mut stems = vec![
+        crate_name.clone(),
+        lib_name.to_string(),
+        format!("lib{lib_name}"),
+        format!("{}{lib_name}", env::consts::DLL_PREFIX),
+        format!("lib-{lib_name}"),
+        format!("lib-{lib_name}-check"),
+        format!("bin-{crate_name}"),
+        format!("bin-{crate_name}-check"),
+    ];
+    let mut test_names = vec!["lib".to_string(), "main".to_string()];
+    if let Ok(tests) = fs::read_dir(package.root.join("tests")) {
+        test_names.extend(tests.filter_map(|test| {
+            let path = test.ok()?.path();
+            (path.extension()? == "rs").then(|| path.file_stem()?.to_str().map(String::from))?
+        }));
+    }
+    for test_name in test_names {
+        stems.push(format!("test-{crate_name}-{test_name}"));
+        stems.push(format!("test-{crate_name}-{test_name}-check"));
+        stems.push(format!("test_{crate_name}_{test_name}"));
+    }
+    stems
+}
+
+/// Every file under `path`, or `path` itself if it's a file, with its size
+fn files(path: &Path) -> Result<Vec<(PathBuf, u64)>> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(Vec::new());
+    };
+    if !metadata.is_dir() {
+        return Ok(vec![(path.to_path_buf(), metadata.len())]);
+    }
+    let mut found = Vec::new();
+    let mut entries = fs::read_dir(path)
+        .map_err(FreightError::io(path))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(FreightError::io(path))?;
+    entries.sort();
+    for entry in entries {
+        found.extend(files(&entry)?);
+    }
+    Ok(found)
+}
+
+/// Show the config values at or under `key`, or every value if there's no
+/// key, along with where they were set if `show_origin` is set
+pub fn config_get(key: Option<&str>, show_origin: bool) -> Result<()> {
+    let dir = match root_dir() {
+        Ok(root) => root,
+        Err(_) => current_dir()?,
+    };
+    let layers = Layers::load(&dir)?;
+    let key = key.unwrap_or_default();
+    let values = layers.values(key);
+    if values.is_empty() {
+        return Err(FreightError::InvalidArgument(format!(
+            "config value `{key}` is not set"
+        )));
+    }
+    let mut logger = Logger::new(MessageFormat::Human);
+    for (key, origin) in values {
+        if let Some(value) = layers.get(key) {
+            if show_origin {
+                logger.output(format_args!("{key} = {value} # {origin}"))?;
+            } else {
+                logger.output(format_args!("{key} = {value}"))?;
             }
         }
     }
+    Ok(())
+}
+
+/// Show how full the compile cache is and how often builds found what they
+/// needed in it
+pub fn cache_stats() -> Result<()> {
+    let config = match root_dir() {
+        Ok(root) => Config::load(root)?,
+        Err(_) => Config::load(current_dir()?)?,
+    };
+    let cache = Cache::new(&config.cache.dir, config.cache.max_size);
+    let stats = cache.stats()?;
+    let lookups = stats.hits + stats.misses;
+    let hit_rate = if lookups == 0 {
+        0.0
+    } else {
+        stats.hits as f64 * 100.0 / lookups as f64
+    };
+    let mut logger = Logger::new(MessageFormat::Human);
+    logger.output(format_args!("   Directory {}", cache.dir().display()))?;
+    logger.output(format_args!(
+        "     Entries {} ({} of {})",
+        stats.entries,
+        cache::format_size(stats.size),
+        cache::format_size(cache.max_size())
+    ))?;
+    logger.output(format_args!("        Hits {}", stats.hits))?;
+    logger.output(format_args!("      Misses {}", stats.misses))?;
+    logger.output(format_args!("    Hit rate {hit_rate:.1}%"))
+}
+
+/// Options for `freight doc` and `freight rustdoc`
+#[derive(Debug, Default)]
+pub struct DocOptions {
+    pub open: bool,
+    /// Document even if rustc is older than a package's `rust-version`
+    pub ignore_rust_version: bool,
+    /// Where output goes instead of the configured target directory
+    pub target_dir: Option<PathBuf>,
+    /// Extra arguments for rustdoc from `freight rustdoc`, passed after all
+    /// the others
+    pub args: Vec<String>,
+}
+
+pub fn doc(options: &DocOptions) -> Result<()> {
+    let mut logger = Logger::new(MessageFormat::Human);
+    let root = root_dir()?;
+    let graph = Graph::resolve(&root)?;
+    if !options.ignore_rust_version {
+        graph.check_rust_version()?;
+    }
+    let layout = Layout::new(&root, options.target_dir.as_deref())?;
+    let lib_path = layout.dest(&CompileKind::Host);
+    let doc_path = layout.doc();
+
+    // Docs only need the libraries of our dependencies to exist
+    let mut units = graph.units(false);
+    units.deps(&CompileKind::Host);
+    let units = units.finish();
+    compile_units(
+        &mut logger,
+        &graph,
+        &units,
+        &layout,
+        |_| lib_path.clone(),
+        false,
+        false,
+    )?;
+
+    // TODO Fix no main.rs
+    let lib = graph
+        .root()
+        .lib_path()
+        .ok_or_else(|| FreightError::MissingTarget("There is no library to document".into()))?;
+    let mut command = rustdoc(&graph, &lib_path, Some(doc_path))?
+        .color(logger.color())
+        .flags(&options.args)
+        .doc_command(lib);
+    logger.command(&command)?;
+    let status = command
+        .status()
+        .map_err(FreightError::io(command.get_program()))?;
+    if status.success() {
+        logger.status(Status::Finished, Profile::DEV.finished(logger.elapsed()))
+    } else {
+        Err(FreightError::CompilerFailed {
+            unit: format!("`{}` (lib doc)", graph.root().manifest.crate_name),
+            status,
+        })
+    }
+}
+
+/// Set up rustdoc for the root package given the directory its dependencies
+/// were compiled into
+fn rustdoc(graph: &Graph, lib_path: &Path, out_path: Option<PathBuf>) -> Result<RustDoc> {
+    let package = graph.root();
+    let config = Config::load(&package.root)?;
+    let mut rustdoc = RustDoc::new(
+        package.manifest.edition,
+        package.lib_name(),
+        lib_path,
+        out_path,
+    )
+    .program(&graph.toolchain.rustdoc)
+    .flags(config.rustdocflags());
+    for dep in &package.deps {
+        let dep = &graph.packages[*dep];
+        rustdoc = rustdoc.externs(format!(
+            "{}={}",
+            dep.lib_name(),
+            lib_path.join(dep.lib_file_name()).display()
+        ));
+    }
+    if package.is_proc_macro() {
+        rustdoc = rustdoc
+            .crate_type(CrateType::ProcMacro)
+            .externs("proc_macro");
+    }
+    Ok(rustdoc)
+}
+
+pub fn build_tests(
+    message_format: MessageFormat,
+    ignore_rust_version: bool,
+    target_dir: Option<&Path>,
+) -> Result<()> {
+    let mut logger = Logger::new(message_format);
+    let root_dir = root_dir()?;
+    let graph = Graph::resolve(&root_dir)?;
+    if !ignore_rust_version {
+        graph.check_rust_version()?;
+    }
+    let mut units = graph.units(false);
+    units.tests(&CompileKind::Host)?;
+    let units = units.finish();
+    if units.is_empty() {
+        return Err(FreightError::MissingTarget(
+            "There is nothing to compile".into(),
+        ));
+    }
+
+    let layout = Layout::new(&root_dir, target_dir)?;
+    let target_tests = layout.tests();
+    compile_units(
+        &mut logger,
+        &graph,
+        &units,
+        &layout,
+        |_| target_tests.clone(),
+        false,
+        false,
+    )?;
 
-    logger.done_compiling()?;
+    logger.status(Status::Finished, Profile::TEST.finished(logger.elapsed()))?;
     Ok(())
 }
 
-pub fn run_tests(test_args: Vec<String>) -> Result<()> {
-    let mut logger = Logger::new();
+pub fn run_tests(
+    test_args: Vec<String>,
+    message_format: MessageFormat,
+    target_dir: Option<&Path>,
+) -> Result<()> {
+    let mut logger = Logger::new(message_format);
     let root = root_dir()?;
-    let manifest = Manifest::parse_from_file(root.join("Freight.toml"))?;
-    let tests_dir = root.join("target").join("debug").join("tests");
+    let graph = Graph::resolve(&root)?;
+    let package = graph.root();
+    let tests_dir = Layout::new(&root, target_dir)?.tests();
+    let prefix = format!("test_{}_", package.manifest.crate_name);
 
-    // Just run the unit tests first
-    for item in tests_dir.read_dir()? {
-        let item = item?;
+    // Just run the unit tests first, then the tests folder
+    let mut unit_tests = Vec::new();
+    let mut integration_tests = Vec::new();
+    for item in tests_dir.read_dir().map_err(FreightError::io(&tests_dir))? {
+        let item = item.map_err(FreightError::io(&tests_dir))?;
         let path = item.path();
         let is_test = path.extension().is_none();
         if is_test {
             let file_name = path.file_name().unwrap().to_str().unwrap();
-            if file_name == "test_freight_main" {
-                logger.main_unit_test()?;
-            } else if file_name == "test_freight_lib" {
-                logger.lib_unit_test()?;
-            } else {
-                continue;
+            // Each with the status line it's run with and its name
+            match file_name.strip_prefix(&prefix) {
+                Some(unit @ ("main" | "lib")) => {
+                    let name = format!("src/{unit}.rs");
+                    unit_tests.push((path, format!("unittests {name}"), name));
+                }
+                Some(test) => {
+                    let name = format!("tests/{test}.rs");
+                    integration_tests.push((path, name.clone(), name));
+                }
+                None => {}
             }
-            Command::new(path).args(&test_args).spawn()?.wait()?;
         }
     }
+    let mut harnesses = unit_tests;
+    harnesses.append(&mut integration_tests);
+    // TODO Fix no main.rs doc tests
+    // Doc tests link against the library so there are none for C ABI libraries
+    let doc_tests = package.lib_path().filter(|_| package.has_rust_lib());
+    let total = harnesses.len() + usize::from(doc_tests.is_some());
 
-    // Then run the tests folder
-    for item in tests_dir.read_dir()? {
-        let item = item?;
-        let path = item.path();
-        let is_test = path.extension().is_none();
-        if is_test {
-            let file_name = path.file_name().unwrap().to_str().unwrap();
-            if file_name == "test_freight_main" {
-                continue;
-            } else if file_name == "test_freight_lib" {
-                continue;
-            } else {
-                logger.tests(&file_name.split('_').last().unwrap())?;
-            }
-            Command::new(path).args(&test_args).spawn()?.wait()?;
-        }
+    for (done, (path, message, name)) in harnesses.into_iter().enumerate() {
+        logger.progress(done, total, &[&name])?;
+        logger.status(Status::Running, message)?;
+        // The harness writes to the terminal itself, under the status line
+        logger.clear_progress()?;
+        run_test(&path, &test_args, name)?;
     }
 
-    let lib = root.join("src").join("lib.rs");
-    // TODO Fix no main.rs doc tests
-    if lib.exists() {
-        logger.doc_test(&manifest.crate_name)?;
-        if !RustDoc::new(
-            manifest.edition,
-            manifest.crate_name,
-            root.join("target").join("debug"),
-            None::<&str>,
-        )
-        .test(lib)?
-        .success()
-        {
-            return Err("Failed to run doc tests".into());
+    if let Some(lib) = doc_tests {
+        logger.progress(total - 1, total, &["doc tests"])?;
+        logger.status(Status::DocTests, &package.manifest.crate_name)?;
+        let mut command = rustdoc(&graph, &tests_dir, None)?
+            .externs(format!(
+                "{}={}",
+                package.lib_name(),
+                tests_dir.join(package.lib_file_name()).display()
+            ))
+            .color(logger.color())
+            .test_command(lib);
+        logger.command(&command)?;
+        logger.clear_progress()?;
+        let status = command
+            .status()
+            .map_err(FreightError::io(command.get_program()))?;
+        if !status.success() {
+            return Err(FreightError::TestFailed {
+                name: format!("doc tests for {}", package.manifest.crate_name),
+                status,
+            });
         }
     }
     Ok(())
 }
 
+/// Run a test harness, stopping at the first one that fails
+fn run_test(path: &Path, test_args: &[String], name: String) -> Result<()> {
+    let status = Command::new(path)
+        .args(test_args)
+        .status()
+        .map_err(FreightError::io(path))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(FreightError::TestFailed { name, status })
+    }
+}
+
+/// Print an error, and everything that caused it, to stderr
+pub fn report(error: &FreightError) {
+    let mut logger = Logger::new(MessageFormat::Human);
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(&format!("\n  caused by: {cause}"));
+        source = cause.source();
+    }
+    // There's nowhere left to report a failure to write to stderr
+    let _ = logger.status(Status::Error, message);
+}
+
+/// The directory Freight was run in
+pub fn current_dir() -> Result<PathBuf> {
+    env::current_dir().map_err(FreightError::io("."))
+}
+
 pub fn root_dir() -> Result<PathBuf> {
-    let current_dir = env::current_dir()?;
+    let current_dir = current_dir()?;
     for ancestor in current_dir.ancestors() {
         if ancestor.join("Freight.toml").exists() {
             return Ok(ancestor.into());
         }
     }
-    Err("No root dir".into())
+    Err(FreightError::ManifestNotFound(current_dir))
 }
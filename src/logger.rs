@@ -1,61 +1,485 @@
+use crate::diagnostic::Diagnostic;
+use crate::diagnostic::Level;
+use crate::json::Value;
+use crate::message;
+use crate::message::Artifact;
+use crate::FreightError;
+use crate::MessageFormat;
 use crate::Result;
+use std::env;
+use std::fmt;
+use std::fmt::Display;
 use std::io;
+use std::io::IsTerminal;
 use std::io::Write;
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How much gets printed, set once for the whole process with `-q`, `-v` or
+/// `-vv`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only errors
+    Quiet,
+    #[default]
+    Normal,
+    /// Also every rustc and rustdoc command, and the units that were fresh
+    Verbose,
+    /// Also what build scripts print. Freight doesn't run build scripts yet so
+    /// for now this is the same as `Verbose`.
+    VeryVerbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Set how much every [`Logger`] made from now on prints
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::VeryVerbose,
+    }
+}
+
+/// Whether output is colored, set once for the whole process with `--color`,
+/// `FREIGHT_TERM_COLOR` or `NO_COLOR`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only when stderr is a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// The choice when `--color` isn't passed: `FREIGHT_TERM_COLOR` if it's
+    /// set, otherwise never if `NO_COLOR` is set and auto if it isn't
+    pub fn from_env() -> Result<Self> {
+        match env::var("FREIGHT_TERM_COLOR") {
+            Ok(choice) => choice.parse().map_err(|error| match error {
+                FreightError::InvalidArgument(message) => {
+                    FreightError::InvalidArgument(format!("FREIGHT_TERM_COLOR: {message}"))
+                }
+                error => error,
+            }),
+            Err(_) if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) => {
+                Ok(Self::Never)
+            }
+            Err(_) => Ok(Self::Auto),
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = FreightError;
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            choice => Err(FreightError::InvalidArgument(format!(
+                "Unsupported color choice {choice}, expected one of auto, always or never"
+            ))),
+        }
+    }
+}
+
+static COLOR: AtomicU8 = AtomicU8::new(ColorChoice::Auto as u8);
+
+/// Set whether every [`Logger`] made from now on colors its output
+pub fn set_color(choice: ColorChoice) {
+    COLOR.store(choice as u8, Ordering::Relaxed);
+}
+
+/// What a status line is about. Most are printed right aligned and in green
+/// before the message like Cargo does, warnings and errors are prefixed in
+/// yellow and red instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Compiling,
+    Checking,
+    /// A unit that didn't need to be compiled, only shown with `-v`
+    Fresh,
+    Running,
+    Finished,
+    DocTests,
+    Artifact,
+    Removing,
+    Removed,
+    Summary,
+    Timing,
+    /// Only shown in the progress bar
+    Building,
+    Warning,
+    Error,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            Self::Compiling => "Compiling",
+            Self::Checking => "Checking",
+            Self::Fresh => "Fresh",
+            Self::Running => "Running",
+            Self::Finished => "Finished",
+            Self::DocTests => "Doc-tests",
+            Self::Artifact => "Artifact",
+            Self::Removing => "Removing",
+            Self::Removed => "Removed",
+            Self::Summary => "Summary",
+            Self::Timing => "Timing",
+            Self::Building => "Building",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        };
+        // Pad the text rather than what's written so `{:>12}` works
+        f.pad(status)
+    }
+}
+
+impl Status {
+    /// The ANSI escape sequence for the status' bold color
+    fn color(self) -> &'static str {
+        match self {
+            Self::Warning => "\x1b[1;33m",
+            Self::Error => "\x1b[1;31m",
+            Self::Building => "\x1b[1;36m",
+            _ => "\x1b[1;32m",
+        }
+    }
+}
 
 pub struct Logger {
-    out: io::StdoutLock<'static>,
+    /// Where the output of commands like `freight config get` goes. Status
+    /// lines go to stderr so that stdout stays clean.
+    out: io::Stdout,
+    err: io::Stderr,
+    /// Color status lines and keep the colors in rustc's rendered diagnostics
+    color: bool,
+    format: MessageFormat,
+    verbosity: Verbosity,
+    /// Whether a progress bar can be drawn on stderr
+    progress_bar: bool,
+    /// The progress bar currently drawn below everything else on stderr
+    progress: Option<String>,
+    /// When the command started, for timing builds
+    start: Instant,
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        // Nothing can be done about a terminal that can't be written to
+        let _ = self.clear_progress();
+    }
 }
 
 impl Logger {
-    pub fn new() -> Self {
+    pub fn new(format: MessageFormat) -> Self {
+        let color = match COLOR.load(Ordering::Relaxed) {
+            0 => io::stderr().is_terminal(),
+            1 => true,
+            _ => false,
+        };
         Self {
-            out: io::stdout().lock(),
+            out: io::stdout(),
+            err: io::stderr(),
+            color,
+            format,
+            verbosity: verbosity(),
+            progress_bar: io::stderr().is_terminal()
+                && verbosity() > Verbosity::Quiet
+                && matches!(format, MessageFormat::Human | MessageFormat::Short)
+                && env::var_os("TERM").is_none_or(|term| term != "dumb"),
+            progress: None,
+            start: Instant::now(),
         }
     }
-    pub fn compiling_crate(&mut self, crate_name: &str) -> Result<()> {
-        self.out
-            .write_all(format!("   Compiling lib {crate_name}\n").as_bytes())?;
-        self.out.flush()?;
+    /// Write to stderr above the progress bar, drawing it again afterwards
+    fn write_err(&mut self, text: &str) -> Result<()> {
+        if self.progress.is_some() {
+            self.err
+                .write_all(b"\r\x1b[K")
+                .map_err(FreightError::output)?;
+        }
+        self.err
+            .write_all(text.as_bytes())
+            .map_err(FreightError::output)?;
+        if let Some(progress) = &self.progress {
+            self.err
+                .write_all(progress.as_bytes())
+                .map_err(FreightError::output)?;
+        }
+        self.err.flush().map_err(FreightError::output)?;
         Ok(())
     }
-    pub fn compiling_bin(&mut self, crate_name: &str) -> Result<()> {
-        self.out
-            .write_all(format!("   Compiling bin {crate_name}\n").as_bytes())?;
-        self.out.flush()?;
+    /// Show how many of the `total` units or test harnesses are done and
+    /// which are running on a line that's redrawn in place, when stderr is a
+    /// terminal
+    pub fn progress(&mut self, done: usize, total: usize, running: &[&str]) -> Result<()> {
+        if !self.progress_bar {
+            return Ok(());
+        }
+        let width = env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+            .unwrap_or(80);
+        let line = progress_line(done, total, running, width);
+        let progress = if self.color {
+            let status = Status::Building;
+            let rest = line.get(12..).unwrap_or_default();
+            format!("{}{status:>12}\x1b[0m{rest}", status.color())
+        } else {
+            line
+        };
+        if self.progress.as_ref() == Some(&progress) {
+            return Ok(());
+        }
+        self.err
+            .write_all(format!("\r{progress}\x1b[K").as_bytes())
+            .map_err(FreightError::output)?;
+        self.err.flush().map_err(FreightError::output)?;
+        self.progress = Some(progress);
         Ok(())
     }
-    pub fn done_compiling(&mut self) -> Result<()> {
-        self.out.write_all(b"    Finished dev\n")?;
-        self.out.flush()?;
+    /// Remove the progress bar, if it's shown
+    pub fn clear_progress(&mut self) -> Result<()> {
+        if self.progress.take().is_some() {
+            self.err
+                .write_all(b"\r\x1b[K")
+                .map_err(FreightError::output)?;
+            self.err.flush().map_err(FreightError::output)?;
+        }
         Ok(())
     }
-    pub fn main_unit_test(&mut self) -> Result<()> {
-        self.unit_test("src/main.rs")?;
-        Ok(())
+    /// Print a status line, unless the verbosity hides it
+    pub fn status(&mut self, status: Status, message: impl Display) -> Result<()> {
+        let shown = match status {
+            Status::Error => true,
+            Status::Fresh => self.verbosity >= Verbosity::Verbose,
+            _ => self.verbosity > Verbosity::Quiet,
+        };
+        if !shown {
+            return Ok(());
+        }
+        let (color, reset) = if self.color {
+            (status.color(), "\x1b[0m")
+        } else {
+            ("", "")
+        };
+        match status {
+            Status::Warning | Status::Error => {
+                self.write_err(&format!("{color}{status}{reset}: {message}\n"))
+            }
+            _ => self.write_err(&format!("{color}{status:>12}{reset} {message}\n")),
+        }
     }
-    pub fn lib_unit_test(&mut self) -> Result<()> {
-        self.unit_test("src/lib.rs")?;
-        Ok(())
+    /// Whether output is colored, which tools Freight runs are told with
+    /// `--color`. rustc can't be when it prints JSON, so it always renders
+    /// diagnostics with colors and they're stripped here instead.
+    pub fn color(&self) -> bool {
+        self.color
     }
-    pub fn tests(&mut self, name: &str) -> Result<()> {
-        self.out.write_all(b"     Running ")?;
-        self.out.write_all(&format!("tests/{name}.rs").as_bytes())?;
-        self.out.write_all(b"\n")?;
-        self.out.flush()?;
+    /// Show a command that's about to run when `-v` is passed
+    pub fn command(&mut self, command: &Command) -> Result<()> {
+        if self.verbosity >= Verbosity::Verbose {
+            self.status(Status::Running, format!("`{}`", command_line(command)))?;
+        }
         Ok(())
     }
-    fn unit_test(&mut self, file: &str) -> Result<()> {
-        self.out.write_all(b"     Running unittests ")?;
-        self.out.write_all(file.as_bytes())?;
-        self.out.write_all(b"\n")?;
-        self.out.flush()?;
-        Ok(())
+    pub fn diagnostic(&mut self, artifact: &Artifact, diagnostic: &Diagnostic) -> Result<()> {
+        if self.verbosity == Verbosity::Quiet
+            && matches!(self.format, MessageFormat::Human | MessageFormat::Short)
+            && !matches!(diagnostic.level, Level::Error | Level::Ice)
+        {
+            return Ok(());
+        }
+        let rendered = match self.format {
+            MessageFormat::Human => match &diagnostic.rendered {
+                Some(rendered) => rendered.clone(),
+                None => return Ok(()),
+            },
+            MessageFormat::Short => diagnostic.short(),
+            // Cargo's messages have plain text, the colors are only for
+            // the terminal
+            MessageFormat::Json => {
+                let mut json = diagnostic.json.clone();
+                if let (Value::Object(message), Some(rendered)) = (&mut json, &diagnostic.rendered)
+                {
+                    message.insert("rendered".into(), strip_ansi(rendered).into());
+                }
+                return self.machine_message(artifact.compiler_message(json));
+            }
+            MessageFormat::JsonDiagnosticShort => {
+                let mut json = diagnostic.json.clone();
+                if let Value::Object(message) = &mut json {
+                    message.insert("rendered".into(), diagnostic.short().into());
+                }
+                return self.machine_message(artifact.compiler_message(json));
+            }
+        };
+        if self.color {
+            self.write_err(&rendered)
+        } else {
+            self.write_err(&strip_ansi(&rendered))
+        }
+    }
+    pub fn compiler_artifact(&mut self, artifact: &Artifact, fresh: bool) -> Result<()> {
+        self.machine_message(artifact.compiler_artifact(fresh))
     }
-    pub fn doc_test(&mut self, crate_name: &str) -> Result<()> {
-        self.out.write_all(b"   Doc-tests ")?;
-        self.out.write_all(crate_name.as_bytes())?;
-        self.out.write_all(b"\n")?;
-        self.out.flush()?;
+    pub fn build_finished(&mut self, success: bool) -> Result<()> {
+        self.machine_message(message::build_finished(success))
+    }
+    /// Print a JSON message on its own line of stdout, which is only done
+    /// with one of the JSON message formats
+    fn machine_message(&mut self, message: Value) -> Result<()> {
+        if matches!(
+            self.format,
+            MessageFormat::Json | MessageFormat::JsonDiagnosticShort
+        ) {
+            let mut stdout = io::stdout().lock();
+            stdout
+                .write_all(format!("{message}\n").as_bytes())
+                .map_err(FreightError::output)?;
+            stdout.flush().map_err(FreightError::output)?;
+        }
         Ok(())
     }
+    /// Pass through something rustc printed that isn't a diagnostic
+    pub fn compiler_output(&mut self, line: &str) -> Result<()> {
+        self.write_err(&format!("{line}\n"))
+    }
+    /// Print a line of a command's output, like `freight config get`'s, to
+    /// stdout
+    pub fn output(&mut self, line: impl Display) -> Result<()> {
+        writeln!(self.out, "{line}").map_err(FreightError::output)?;
+        self.out.flush().map_err(FreightError::output)
+    }
+    /// How long it's been since the command started
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// The progress bar, like `    Building [===>   ] 2/5: foo, bar`, cut off to
+/// fit in `width` columns
+fn progress_line(done: usize, total: usize, running: &[&str], width: usize) -> String {
+    const BAR: usize = 25;
+    let filled = (BAR * done).checked_div(total).unwrap_or(BAR);
+    let bar = if filled < BAR {
+        format!("{}>{}", "=".repeat(filled), " ".repeat(BAR - filled - 1))
+    } else {
+        "=".repeat(BAR)
+    };
+    let mut line = format!("{:>12} [{bar}] {done}/{total}", Status::Building);
+    if !running.is_empty() {
+        line.push_str(": ");
+        line.push_str(&running.join(", "));
+    }
+    // Leave the last column free so the terminal doesn't wrap the line
+    line.chars().take(width.saturating_sub(1)).collect()
+}
+
+/// A command as it could be typed into a shell, with the environment
+/// variables it sets in front
+fn command_line(command: &Command) -> String {
+    let mut words = Vec::new();
+    for (name, value) in command.get_envs() {
+        if let Some(value) = value {
+            words.push(format!(
+                "{}={}",
+                name.to_string_lossy(),
+                quote(&value.to_string_lossy())
+            ));
+        }
+    }
+    words.push(quote(&command.get_program().to_string_lossy()));
+    for arg in command.get_args() {
+        words.push(quote(&arg.to_string_lossy()));
+    }
+    words.join(" ")
+}
+
+/// Single quote a word if a shell would split it up or expand it
+pub(crate) fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_=+,./:@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// `count` with `noun`, made plural unless there's one
+pub(crate) fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+/// Remove the ANSI escape sequences rustc colors its diagnostics with
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            stripped.push(c);
+            continue;
+        }
+        if chars.next() == Some('[') {
+            // Everything up to and including the final byte of the sequence
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    stripped
+}
+
+#[test]
+fn progress_lines() {
+    assert_eq!(
+        progress_line(2, 5, &["foo", "bar"], 80),
+        "    Building [==========>              ] 2/5: foo, bar"
+    );
+    assert_eq!(
+        progress_line(5, 5, &[], 80),
+        "    Building [=========================] 5/5"
+    );
+    assert_eq!(progress_line(0, 5, &["foo"], 20), "    Building [>    ");
+}
+
+#[test]
+fn command_lines() {
+    let mut command = Command::new("rustc");
+    command
+        .env("RUSTC_BOOTSTRAP", "1")
+        .args(["src/lib.rs", "--cfg", "feature=\"a b\"", ""]);
+    assert_eq!(
+        command_line(&command),
+        "RUSTC_BOOTSTRAP=1 rustc src/lib.rs --cfg 'feature=\"a b\"' ''"
+    );
+    assert_eq!(quote("it's"), "'it'\\''s'");
+}
+
+#[test]
+fn strip_ansi_colors() {
+    assert_eq!(
+        strip_ansi("\u{1b}[1m\u{1b}[33mwarning\u{1b}[0m\u{1b}[1m: unused\u{1b}[0m\n"),
+        "warning: unused\n"
+    );
+    assert_eq!(strip_ansi("no colors"), "no colors");
 }
@@ -1,44 +1,404 @@
+use freight::ColorChoice;
+use freight::Result;
+use freight::Verbosity;
 use std::env;
-use std::error::Error;
-use std::fs;
+use std::path::PathBuf;
 use std::process;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    const HELP: &str = include_str!("help.txt");
+const HELP: &str = include_str!("help.txt");
 
-    let mut args = env::args().skip(1);
+fn main() {
+    if let Err(error) = run() {
+        freight::report(&error);
+        process::exit(1);
+    }
+}
+
+/// Whether `arg` is one of the flags every command takes
+fn is_global_flag(arg: &str) -> bool {
+    matches!(
+        arg,
+        "-v" | "--verbose" | "-vv" | "-q" | "--quiet" | "--color"
+    ) || arg.starts_with("--color=")
+}
+
+/// How many of the arguments are Freight's own, rather than ones for a
+/// program it runs. Those start after a `--`, and for `freight run` at the
+/// first argument that isn't one of its flags.
+fn own_args_len(args: &[String]) -> usize {
+    let end = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    let mut index = 0;
+    let mut command = None;
+    while index < end {
+        let arg = args[index].as_str();
+        let takes_value = matches!(arg, "--color" | "--message-format" | "--target-dir");
+        match command {
+            _ if is_global_flag(arg) => {}
+            None if arg.starts_with('-') || arg.starts_with('+') => {}
+            None => command = Some(arg),
+            Some("run")
+                if takes_value
+                    || matches!(arg, "--ignore-rust-version" | "--timings")
+                    || arg.starts_with("--message-format=") => {}
+            Some("run") => return index,
+            Some(_) => {}
+        }
+        index += if takes_value { 2 } else { 1 };
+    }
+    end
+}
+
+fn run() -> Result<()> {
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    // -v, -vv, -q and --color can go anywhere among Freight's own arguments,
+    // like Cargo's global flags
+    let mut rest = args.split_off(own_args_len(&args));
+    let mut verbose = 0;
+    let mut quiet = false;
+    let mut color = None;
+    let mut needs_color = false;
+    args.retain(|arg| match arg.as_str() {
+        _ if needs_color => {
+            needs_color = false;
+            color = Some(arg.clone());
+            false
+        }
+        "--color" => {
+            needs_color = true;
+            false
+        }
+        arg if arg.starts_with("--color=") => {
+            color = Some(arg["--color=".len()..].to_string());
+            false
+        }
+        "-v" | "--verbose" => {
+            verbose += 1;
+            false
+        }
+        "-vv" => {
+            verbose += 2;
+            false
+        }
+        "-q" | "--quiet" => {
+            quiet = true;
+            false
+        }
+        _ => true,
+    });
+    args.append(&mut rest);
+    if needs_color {
+        println!("--color requires auto, always or never");
+        process::exit(1);
+    }
+    freight::set_color(match color {
+        Some(color) => color.parse()?,
+        None => ColorChoice::from_env()?,
+    });
+    freight::set_verbosity(match (quiet, verbose) {
+        (false, 0) => Verbosity::Normal,
+        (false, 1) => Verbosity::Verbose,
+        (false, _) => Verbosity::VeryVerbose,
+        (true, 0) => Verbosity::Quiet,
+        (true, _) => {
+            println!("-q and -v can't be used together");
+            process::exit(1);
+        }
+    });
+    let mut args = args.into_iter().peekable();
+    // `freight +nightly build` picks the toolchain the same way rustup does
+    // for `cargo +nightly build`, so rustc and rustdoc proxies pick it too
+    if let Some(toolchain) = args.next_if(|arg| arg.starts_with('+')) {
+        env::set_var("RUSTUP_TOOLCHAIN", &toolchain[1..]);
+    }
     match args.next().as_ref().map(String::as_str) {
         Some("new") => {
             if let Some(dir) = &args.next() {
-                fs::create_dir_all(&dir)?;
-                freight::init(&dir)?;
+                freight::new(dir)?;
             } else {
                 println!("No directory given");
                 process::exit(1);
             }
         }
-        Some("init") => freight::init(&env::current_dir()?)?,
-        Some("run") => freight::run(args.collect::<Vec<String>>())?,
-        Some("build") => freight::build()?,
-        Some("doc") => {
-            let open = args
-                .next()
-                .as_ref()
-                .map(String::as_str)
-                .map(|open| open == "--open")
-                .unwrap_or(false);
-            freight::doc(open)?
+        Some("init") => freight::init(freight::current_dir()?)?,
+        Some("run") => {
+            // Only the flags before the first argument for the binary are ours
+            let mut options = freight::BuildOptions::default();
+            let mut run_args = Vec::new();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--message-format" => match args.next() {
+                        Some(format) => options.message_format = format.parse()?,
+                        None => {
+                            println!("--message-format requires a format");
+                            process::exit(1);
+                        }
+                    },
+                    "--ignore-rust-version" => options.ignore_rust_version = true,
+                    "--timings" => options.timings = true,
+                    "--target-dir" => match args.next() {
+                        Some(dir) => options.target_dir = Some(dir.into()),
+                        None => {
+                            println!("--target-dir requires a directory");
+                            process::exit(1);
+                        }
+                    },
+                    "--" => run_args.extend(args.by_ref()),
+                    arg => match arg.strip_prefix("--message-format=") {
+                        Some(format) => options.message_format = format.parse()?,
+                        None => {
+                            run_args.push(arg.to_string());
+                            run_args.extend(args.by_ref());
+                        }
+                    },
+                }
+            }
+            process::exit(freight::run(&options, run_args)?)
+        }
+        Some("build") => {
+            let mut options = freight::BuildOptions::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--target" => match args.next() {
+                        Some(target) => options.targets.push(target),
+                        None => {
+                            println!("--target requires a target triple or spec file");
+                            process::exit(1);
+                        }
+                    },
+                    "--message-format" => match args.next() {
+                        Some(format) => options.message_format = format.parse()?,
+                        None => {
+                            println!("--message-format requires a format");
+                            process::exit(1);
+                        }
+                    },
+                    "--ignore-rust-version" => options.ignore_rust_version = true,
+                    "--timings" => options.timings = true,
+                    "--target-dir" => match args.next() {
+                        Some(dir) => options.target_dir = Some(dir.into()),
+                        None => {
+                            println!("--target-dir requires a directory");
+                            process::exit(1);
+                        }
+                    },
+                    "--artifact-dir" => match args.next() {
+                        Some(dir) => options.artifact_dir = Some(dir.into()),
+                        None => {
+                            println!("--artifact-dir requires a directory");
+                            process::exit(1);
+                        }
+                    },
+                    arg => {
+                        if let Some(target) = arg.strip_prefix("--target=") {
+                            options.targets.push(target.into());
+                        } else if let Some(format) = arg.strip_prefix("--message-format=") {
+                            options.message_format = format.parse()?;
+                        } else {
+                            println!("Unsupported argument {arg}");
+                            process::exit(1);
+                        }
+                    }
+                }
+            }
+            freight::build(&options)?
+        }
+        Some("check") => {
+            let mut options = freight::CheckOptions::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--tests" => options.tests = true,
+                    "--all-targets" => options.all_targets = true,
+                    "--target" => match args.next() {
+                        Some(target) => options.targets.push(target),
+                        None => {
+                            println!("--target requires a target triple or spec file");
+                            process::exit(1);
+                        }
+                    },
+                    "--message-format" => match args.next() {
+                        Some(format) => options.message_format = format.parse()?,
+                        None => {
+                            println!("--message-format requires a format");
+                            process::exit(1);
+                        }
+                    },
+                    "--ignore-rust-version" => options.ignore_rust_version = true,
+                    "--timings" => options.timings = true,
+                    "--target-dir" => match args.next() {
+                        Some(dir) => options.target_dir = Some(dir.into()),
+                        None => {
+                            println!("--target-dir requires a directory");
+                            process::exit(1);
+                        }
+                    },
+                    arg => {
+                        if let Some(target) = arg.strip_prefix("--target=") {
+                            options.targets.push(target.into());
+                        } else if let Some(format) = arg.strip_prefix("--message-format=") {
+                            options.message_format = format.parse()?;
+                        } else {
+                            println!("Unsupported argument {arg}");
+                            process::exit(1);
+                        }
+                    }
+                }
+            }
+            freight::check(&options)?
+        }
+        Some(command @ ("doc" | "rustdoc")) => {
+            let mut options = freight::DocOptions::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--open" => options.open = true,
+                    "--ignore-rust-version" => options.ignore_rust_version = true,
+                    "--target-dir" => match args.next() {
+                        Some(dir) => options.target_dir = Some(dir.into()),
+                        None => {
+                            println!("--target-dir requires a directory");
+                            process::exit(1);
+                        }
+                    },
+                    "--" if command == "rustdoc" => options.args.extend(args.by_ref()),
+                    arg => {
+                        println!("Unsupported argument {arg}");
+                        process::exit(1);
+                    }
+                }
+            }
+            freight::doc(&options)?
+        }
+        Some("rustc") => {
+            let mut options = freight::RustcOptions::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--lib" => options.filter = Some(freight::CrateFilter::Lib),
+                    "--ignore-rust-version" => options.ignore_rust_version = true,
+                    "--bin" | "--test" | "--print" | "--crate-type" | "--target"
+                    | "--target-dir" | "--message-format" => match args.next() {
+                        Some(value) => match arg.as_str() {
+                            "--bin" => options.filter = Some(freight::CrateFilter::Bin(value)),
+                            "--test" => options.filter = Some(freight::CrateFilter::Test(value)),
+                            "--print" => options.print = Some(value),
+                            "--crate-type" => options
+                                .crate_types
+                                .extend(value.split(',').map(String::from)),
+                            "--target" => options.targets.push(value),
+                            "--target-dir" => options.target_dir = Some(value.into()),
+                            _ => options.message_format = value.parse()?,
+                        },
+                        None => {
+                            println!("{arg} requires a value");
+                            process::exit(1);
+                        }
+                    },
+                    "--" => options.args.extend(args.by_ref()),
+                    arg => {
+                        println!("Unsupported argument {arg}");
+                        process::exit(1);
+                    }
+                }
+            }
+            freight::rustc(&options)?
         }
         Some("test") => {
-            freight::build_tests()?;
+            let mut message_format = freight::MessageFormat::default();
+            let mut ignore_rust_version = false;
+            let mut target_dir = None;
             loop {
                 match args.next().as_ref().map(String::as_str) {
                     Some("--") | None => break,
-                    _ => continue,
+                    Some("--message-format") => match args.next() {
+                        Some(format) => message_format = format.parse()?,
+                        None => {
+                            println!("--message-format requires a format");
+                            process::exit(1);
+                        }
+                    },
+                    Some("--ignore-rust-version") => ignore_rust_version = true,
+                    Some("--target-dir") => match args.next() {
+                        Some(dir) => target_dir = Some(PathBuf::from(dir)),
+                        None => {
+                            println!("--target-dir requires a directory");
+                            process::exit(1);
+                        }
+                    },
+                    Some(arg) => {
+                        if let Some(format) = arg.strip_prefix("--message-format=") {
+                            message_format = format.parse()?;
+                        }
+                    }
                 }
             }
-            freight::run_tests(args.collect::<Vec<String>>())?
+            freight::build_tests(message_format, ignore_rust_version, target_dir.as_deref())?;
+            freight::run_tests(
+                args.collect::<Vec<String>>(),
+                message_format,
+                target_dir.as_deref(),
+            )?
         }
+        Some("clean") => {
+            let mut options = freight::CleanOptions::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--release" => options.release = true,
+                    "--doc" => options.doc = true,
+                    "--dry-run" => options.dry_run = true,
+                    "--gc" => options.gc = true,
+                    "--profile" | "--target" | "--target-dir" | "-p" | "--package" => {
+                        match args.next() {
+                            Some(value) => match arg.as_str() {
+                                "--profile" => options.profile = Some(value),
+                                "--target" => options.targets.push(value),
+                                "--target-dir" => options.target_dir = Some(value.into()),
+                                _ => options.packages.push(value),
+                            },
+                            None => {
+                                println!("{arg} requires a value");
+                                process::exit(1);
+                            }
+                        }
+                    }
+                    arg => {
+                        println!("Unsupported argument {arg}");
+                        process::exit(1);
+                    }
+                }
+            }
+            freight::clean(&options)?
+        }
+        Some("config") => match args.next().as_deref() {
+            Some("get") => {
+                let mut key = None;
+                let mut show_origin = false;
+                for arg in args {
+                    match arg.as_str() {
+                        "--show-origin" => show_origin = true,
+                        arg if key.is_none() && !arg.starts_with('-') => {
+                            key = Some(arg.to_string())
+                        }
+                        arg => {
+                            println!("Unsupported argument {arg}");
+                            process::exit(1);
+                        }
+                    }
+                }
+                freight::config_get(key.as_deref(), show_origin)?
+            }
+            _ => {
+                println!("Unsupported config command, expected `freight config get`");
+                process::exit(1);
+            }
+        },
+        Some("cache") => match args.next().as_deref() {
+            Some("stats") => freight::cache_stats()?,
+            _ => {
+                println!("Unsupported cache command, expected `freight cache stats`");
+                process::exit(1);
+            }
+        },
         Some("help") => println!("{HELP}"),
         _ => {
             println!("Unsupported command");
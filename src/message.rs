@@ -0,0 +1,98 @@
+//! The JSON messages printed with `--message-format json`. They follow the
+//! schema of Cargo's messages so tools written for Cargo can read them.
+//! There's no `build-script-executed` message since Freight doesn't run
+//! build scripts.
+use crate::json::Value;
+use crate::profile::Profile;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// What the messages about a unit say about it: the package and target it
+/// belongs to and what it produces
+#[derive(Debug)]
+pub struct Artifact {
+    pub package_id: String,
+    pub manifest_path: PathBuf,
+    /// The kind of target, like `lib`, `bin`, `test` or `proc-macro`
+    pub kind: Vec<String>,
+    pub crate_types: Vec<String>,
+    pub name: String,
+    pub src_path: PathBuf,
+    pub edition: String,
+    /// Whether the unit is a test harness
+    pub test: bool,
+    pub profile: Profile,
+    pub filenames: Vec<PathBuf>,
+    pub executable: Option<PathBuf>,
+}
+
+impl Artifact {
+    pub fn compiler_artifact(&self, fresh: bool) -> Value {
+        Value::from([
+            ("reason", "compiler-artifact".into()),
+            ("package_id", self.package_id.as_str().into()),
+            ("manifest_path", path(&self.manifest_path)),
+            ("target", self.target()),
+            (
+                "profile",
+                Value::from([
+                    ("opt_level", self.profile.opt_level.into()),
+                    ("debuginfo", Value::Number(self.profile.debuginfo.into())),
+                    ("debug_assertions", self.profile.debug_assertions.into()),
+                    ("overflow_checks", self.profile.overflow_checks.into()),
+                    ("test", self.test.into()),
+                ]),
+            ),
+            ("features", Value::Array(Vec::new())),
+            (
+                "filenames",
+                Value::Array(self.filenames.iter().map(|file| path(file)).collect()),
+            ),
+            (
+                "executable",
+                self.executable
+                    .as_ref()
+                    .map(|executable| path(executable))
+                    .unwrap_or(Value::Null),
+            ),
+            ("fresh", fresh.into()),
+        ])
+    }
+
+    /// Wrap a diagnostic as rustc printed it
+    pub fn compiler_message(&self, message: Value) -> Value {
+        Value::from([
+            ("reason", "compiler-message".into()),
+            ("package_id", self.package_id.as_str().into()),
+            ("manifest_path", path(&self.manifest_path)),
+            ("target", self.target()),
+            ("message", message),
+        ])
+    }
+
+    fn target(&self) -> Value {
+        Value::from([
+            ("kind", self.kind.clone().into()),
+            ("crate_types", self.crate_types.clone().into()),
+            ("name", self.name.as_str().into()),
+            ("src_path", path(&self.src_path)),
+            ("edition", self.edition.as_str().into()),
+            (
+                "doctest",
+                (self.kind.iter().any(|kind| kind == "lib")).into(),
+            ),
+            ("test", true.into()),
+        ])
+    }
+}
+
+pub fn build_finished(success: bool) -> Value {
+    Value::from([
+        ("reason", "build-finished".into()),
+        ("success", success.into()),
+    ])
+}
+
+fn path(path: &Path) -> Value {
+    path.display().to_string().into()
+}
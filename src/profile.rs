@@ -0,0 +1,77 @@
+//! The profiles units are compiled with. Like Cargo, `dev` is used for
+//! libraries and binaries and `test` for test harnesses, which has the same
+//! settings. No flags are passed for them, so the settings are rustc's
+//! defaults.
+use crate::graph::UnitTarget;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    pub name: &'static str,
+    pub opt_level: &'static str,
+    /// 0 for none, 1 for line tables only and 2 for full debuginfo
+    pub debuginfo: u32,
+    /// rustc turns these on whenever `opt_level` is 0
+    pub debug_assertions: bool,
+    pub overflow_checks: bool,
+}
+
+impl Profile {
+    pub const DEV: Self = Self {
+        name: "dev",
+        opt_level: "0",
+        debuginfo: 0,
+        debug_assertions: true,
+        overflow_checks: true,
+    };
+    pub const TEST: Self = Self {
+        name: "test",
+        ..Self::DEV
+    };
+
+    /// The profile a unit with this target is compiled with
+    pub fn for_target(target: UnitTarget) -> Self {
+        match target {
+            UnitTarget::Lib | UnitTarget::Bin => Self::DEV,
+            UnitTarget::Test => Self::TEST,
+        }
+    }
+
+    /// Describes the settings the way Cargo does when a build finishes, like
+    /// `unoptimized + debuginfo`
+    pub fn description(&self) -> String {
+        let mut settings = vec![if self.opt_level == "0" {
+            "unoptimized"
+        } else {
+            "optimized"
+        }];
+        if self.debuginfo > 0 {
+            settings.push("debuginfo");
+        }
+        settings.join(" + ")
+    }
+
+    /// What the `Finished` line says after a build with the profile that
+    /// took `elapsed`
+    pub fn finished(&self, elapsed: Duration) -> String {
+        format!(
+            "{} [{}] target(s) in {:.2}s",
+            self.name,
+            self.description(),
+            elapsed.as_secs_f64()
+        )
+    }
+}
+
+#[test]
+fn descriptions() {
+    assert_eq!(Profile::DEV.description(), "unoptimized");
+    let release = Profile {
+        name: "release",
+        opt_level: "3",
+        debuginfo: 2,
+        debug_assertions: false,
+        overflow_checks: false,
+    };
+    assert_eq!(release.description(), "optimized + debuginfo");
+}
@@ -1,21 +1,37 @@
-use super::BoxError;
+use super::FreightError;
 use super::Result;
+use crate::semver::Version;
+use crate::toolchain::Toolchain;
+use std::ffi::OsStr;
 use std::fmt;
 use std::fmt::Display;
+use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 use std::process::ExitStatus;
 use std::str::FromStr;
 
+#[derive(Clone)]
 pub struct Rustc {
     edition: Edition,
-    crate_type: CrateType,
+    crate_types: Vec<CrateType>,
     crate_name: String,
     out_dir: PathBuf,
-    lib_dir: PathBuf,
+    lib_dirs: Vec<PathBuf>,
     cfg: Vec<String>,
     externs: Vec<String>,
     test: bool,
+    target: Option<String>,
+    emit: Vec<(Emit, Option<PathBuf>)>,
+    json: bool,
+    linker: Option<PathBuf>,
+    /// Native libraries to link, passed with `-l`
+    link_libs: Vec<String>,
+    /// Folders to search for native libraries, passed with `-L native=`
+    link_search: Vec<PathBuf>,
+    /// Extra flags from `RUSTFLAGS` and the config, passed last
+    flags: Vec<String>,
+    toolchain: Toolchain,
 }
 
 impl Rustc {
@@ -42,48 +58,151 @@ impl Rustc {
     }
 
     pub fn run(self, path: &str) -> Result<ExitStatus> {
-        let exit_status = Command::new("rustc")
+        let mut command = self.command(path);
+        command
+            .status()
+            .map_err(FreightError::io(command.get_program()))
+    }
+
+    /// The full command used to invoke rustc on the crate root at `path`
+    pub fn command(&self, path: &str) -> Command {
+        let mut command = self.toolchain.rustc_command();
+        command
             .arg(path)
             .arg("--edition")
             .arg(self.edition.to_string())
-            .arg("--crate-type")
-            .arg(self.crate_type.to_string())
+            .args(
+                self.crate_types
+                    .iter()
+                    .flat_map(|crate_type| ["--crate-type".into(), crate_type.to_string()]),
+            )
             .arg("--crate-name")
-            .arg(self.crate_name)
+            .arg(&self.crate_name)
             .arg("--out-dir")
-            .arg(self.out_dir)
-            .arg("-L")
-            .arg(self.lib_dir)
+            .arg(&self.out_dir)
+            .args(
+                self.lib_dirs
+                    .iter()
+                    .flat_map(|lib_dir| [OsStr::new("-L"), lib_dir.as_os_str()]),
+            )
             .args(if self.test { vec!["--test"] } else { vec![] })
             .args(
-                self.externs
-                    .into_iter()
-                    .map(|r#extern| ["--extern".into(), r#extern])
-                    .flatten(),
+                self.target
+                    .iter()
+                    .flat_map(|target| ["--target", target.as_str()]),
             )
             .args(
-                self.cfg
-                    .into_iter()
-                    .map(|cfg| ["--cfg".into(), cfg])
-                    .flatten(),
+                self.externs
+                    .iter()
+                    .flat_map(|r#extern| ["--extern", r#extern.as_str()]),
             )
-            .spawn()?
-            .wait()?;
+            .args(self.cfg.iter().flat_map(|cfg| ["--cfg", cfg.as_str()]))
+            .args(self.link_libs.iter().flat_map(|lib| ["-l", lib.as_str()]))
+            .args(
+                self.link_search
+                    .iter()
+                    .flat_map(|dir| ["-L".into(), format!("native={}", dir.display())]),
+            );
+        if let Some(linker) = &self.linker {
+            command
+                .arg("-C")
+                .arg(format!("linker={}", linker.display()));
+        }
+
+        if !self.emit.is_empty() {
+            let emit = self
+                .emit
+                .iter()
+                .map(|(emit, path)| match path {
+                    Some(path) => format!("{emit}={}", path.display()),
+                    None => emit.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            command.arg(format!("--emit={emit}"));
+        }
+
+        if self.json {
+            command
+                .arg("--error-format=json")
+                .arg("--json=diagnostic-rendered-ansi,artifacts");
+        }
+
+        command.args(&self.flags);
+        command
+    }
 
-        Ok(exit_status)
+    /// A command that only writes the dep-info for the crate root at `path` to
+    /// `dep_info`, which lists the sources without compiling anything
+    pub fn dep_info_command(&self, path: &str, dep_info: &Path) -> Command {
+        let mut rustc = self.clone();
+        rustc.emit = vec![(Emit::DepInfo, Some(dep_info.into()))];
+        rustc.json = false;
+        rustc.toolchain.wrapper = None;
+        rustc.command(path)
+    }
+
+    /// The files rustc will produce in the out dir for each crate type, in the
+    /// same order the crate types were given in. Crate types the target
+    /// doesn't support produce nothing.
+    pub fn file_names(&self, path: &str) -> Result<Vec<PathBuf>> {
+        let output = Command::new(&self.toolchain.rustc)
+            .arg(path)
+            .arg("--print")
+            .arg("file-names")
+            .arg("--edition")
+            .arg(self.edition.to_string())
+            .args(
+                self.crate_types
+                    .iter()
+                    .flat_map(|crate_type| ["--crate-type".into(), crate_type.to_string()]),
+            )
+            .arg("--crate-name")
+            .arg(&self.crate_name)
+            .args(
+                self.target
+                    .iter()
+                    .flat_map(|target| ["--target", target.as_str()]),
+            )
+            .args(&self.flags)
+            .output()
+            .map_err(FreightError::io(
+                self.toolchain.rustc_command().get_program(),
+            ))?;
+        if !output.status.success() {
+            return Err(FreightError::Tool {
+                program: "rustc".into(),
+                message: format!(
+                    "could not get the output file names for {}",
+                    self.crate_name
+                ),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|name| self.out_dir.join(name))
+            .collect())
     }
 }
 
 #[derive(Default)]
 pub struct RustcBuilder {
     edition: Option<Edition>,
-    crate_type: Option<CrateType>,
+    crate_types: Vec<CrateType>,
     crate_name: Option<String>,
     out_dir: Option<PathBuf>,
-    lib_dir: Option<PathBuf>,
+    lib_dirs: Vec<PathBuf>,
     cfg: Vec<String>,
     externs: Vec<String>,
     test: bool,
+    target: Option<String>,
+    emit: Vec<(Emit, Option<PathBuf>)>,
+    json: bool,
+    linker: Option<PathBuf>,
+    link_libs: Vec<String>,
+    link_search: Vec<PathBuf>,
+    flags: Vec<String>,
+    toolchain: Toolchain,
 }
 
 impl RustcBuilder {
@@ -95,16 +214,20 @@ impl RustcBuilder {
         self.out_dir = Some(out_dir.into());
         self
     }
+    /// Add a directory to search for dependencies in. Can be called more than
+    /// once.
     pub fn lib_dir(mut self, lib_dir: impl Into<PathBuf>) -> Self {
-        self.lib_dir = Some(lib_dir.into());
+        self.lib_dirs.push(lib_dir.into());
         self
     }
     pub fn crate_name(mut self, crate_name: impl Into<String>) -> Self {
         self.crate_name = Some(crate_name.into());
         self
     }
+    /// Add a crate type to emit. Can be called more than once to build several
+    /// kinds of library in one invocation.
     pub fn crate_type(mut self, crate_type: CrateType) -> Self {
-        self.crate_type = Some(crate_type);
+        self.crate_types.push(crate_type);
         self
     }
     pub fn cfg(mut self, cfg: impl Into<String>) -> Self {
@@ -121,25 +244,269 @@ impl RustcBuilder {
         self
     }
 
+    /// The target triple, or path to a target spec JSON file, to compile for.
+    /// The host is used if this is never called.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Add an output for rustc to emit. rustc only links the crate if this is
+    /// never called.
+    pub fn emit(mut self, emit: Emit) -> Self {
+        self.emit.push((emit, None));
+        self
+    }
+
+    /// Add an output for rustc to emit at `path` instead of the out dir
+    pub fn emit_to(mut self, emit: Emit, path: impl Into<PathBuf>) -> Self {
+        self.emit.push((emit, Some(path.into())));
+        self
+    }
+
+    /// Have rustc print diagnostics, and a notification whenever it finishes
+    /// writing one of the outputs it was asked to emit, as JSON on stderr
+    pub fn json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    /// The linker for rustc to run instead of its default
+    pub fn linker(mut self, linker: impl Into<PathBuf>) -> Self {
+        self.linker = Some(linker.into());
+        self
+    }
+
+    /// Link a native library, like `foo` or `static=foo`. Can be called more
+    /// than once.
+    pub fn link_lib(mut self, lib: impl Into<String>) -> Self {
+        self.link_libs.push(lib.into());
+        self
+    }
+
+    /// Add a directory to search for native libraries in. Can be called more
+    /// than once.
+    pub fn link_search(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.link_search.push(dir.into());
+        self
+    }
+
+    /// Pass extra flags to rustc after the ones Freight sets. Can be called
+    /// more than once.
+    pub fn flags(mut self, flags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.flags.extend(flags.into_iter().map(Into::into));
+        self
+    }
+
+    /// The rustc, and wrapper, to run. The one on the `PATH` is used if this
+    /// is never called.
+    pub fn toolchain(mut self, toolchain: &Toolchain) -> Self {
+        self.toolchain = toolchain.clone();
+        self
+    }
+
     pub fn done(self) -> Rustc {
+        assert!(!self.crate_types.is_empty(), "Crate type given");
         Rustc {
             edition: self.edition.unwrap_or(Edition::E2015),
-            crate_type: self.crate_type.expect("Crate type given"),
+            crate_types: self.crate_types,
             crate_name: self.crate_name.expect("Crate name given"),
             out_dir: self.out_dir.expect("Out dir given"),
-            lib_dir: self.lib_dir.expect("Lib dir given"),
+            lib_dirs: self.lib_dirs,
             cfg: self.cfg,
             externs: self.externs,
             test: self.test,
+            target: self.target,
+            emit: self.emit,
+            json: self.json,
+            linker: self.linker,
+            link_libs: self.link_libs,
+            link_search: self.link_search,
+            flags: self.flags,
+            toolchain: self.toolchain,
         }
     }
 }
 
+/// The kinds of output rustc can be asked to `--emit`
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// The final artifact for the crate type, like an rlib or executable
+    Link,
+    /// Only the crate metadata, which is enough to type check dependents
+    Metadata,
+    /// A Makefile style list of every source file read
+    DepInfo,
+}
+
+impl Display for Emit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let emit = match self {
+            Self::Link => "link",
+            Self::Metadata => "metadata",
+            Self::DepInfo => "dep-info",
+        };
+        write!(f, "{emit}")
+    }
+}
+
+/// A target to cross compile for, either a triple rustc knows about or a path
+/// to a custom target spec JSON file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CompileTarget {
+    name: String,
+    rustc_target: String,
+}
+
+impl CompileTarget {
+    /// Validate `target` against `rustc --print target-list`, or check that the
+    /// spec file exists if it ends in `.json`
+    pub fn new(target: &str, toolchain: &Toolchain) -> Result<Self> {
+        if target.ends_with(".json") {
+            let path = Path::new(target);
+            let spec = path.canonicalize().map_err(FreightError::io(path))?;
+            let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+            return Ok(Self {
+                name,
+                rustc_target: spec.to_str().unwrap().to_string(),
+            });
+        }
+
+        if target_list(toolchain)?.iter().any(|known| known == target) {
+            Ok(Self {
+                name: target.to_string(),
+                rustc_target: target.to_string(),
+            })
+        } else {
+            Err(FreightError::UnsupportedTarget(format!(
+                "Target {target} is not supported by rustc. \
+                 Run `rustc --print target-list` to see the supported targets"
+            )))
+        }
+    }
+
+    /// The name used for the output directory, `target/<name>/debug`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value to pass to rustc's `--target` flag
+    pub fn rustc_target(&self) -> &str {
+        &self.rustc_target
+    }
+}
+
+/// Every target triple the toolchain's rustc supports
+pub fn target_list(toolchain: &Toolchain) -> Result<Vec<String>> {
+    let output = Command::new(&toolchain.rustc)
+        .arg("--print")
+        .arg("target-list")
+        .output()
+        .map_err(FreightError::io(&toolchain.rustc))?;
+    if !output.status.success() {
+        return Err(FreightError::Tool {
+            program: "rustc".into(),
+            message: "could not get the target list".into(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// What `rustc -vV` says about the installed compiler
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RustcVersion {
+    pub version: Version,
+    /// The commit rustc was built from, which tells nightlies apart
+    pub commit_hash: Option<String>,
+    /// The target triple rustc runs on
+    pub host: String,
+    pub llvm_version: Option<String>,
+}
+
+impl RustcVersion {
+    pub fn parse(output: &str) -> std::result::Result<Self, String> {
+        let field = |name: &str| {
+            output.lines().find_map(|line| {
+                line.strip_prefix(name)?
+                    .strip_prefix(": ")
+                    .map(|value| value.trim().to_string())
+            })
+        };
+        let release = field("release").ok_or("`rustc -vV` has no release")?;
+        Ok(Self {
+            version: release.parse()?,
+            commit_hash: field("commit-hash").filter(|hash| hash != "unknown"),
+            host: field("host").ok_or("`rustc -vV` has no host")?,
+            llvm_version: field("LLVM version"),
+        })
+    }
+
+    /// Whether rustc is `version` or newer. Nightlies and betas count as the
+    /// release they lead up to.
+    pub fn at_least(&self, version: &Version) -> bool {
+        Version::new(self.version.major, self.version.minor, self.version.patch) >= *version
+    }
+}
+
+/// Ask the toolchain's rustc what version it is
+pub fn version(toolchain: &Toolchain) -> Result<RustcVersion> {
+    let output = Command::new(&toolchain.rustc)
+        .arg("-vV")
+        .output()
+        .map_err(FreightError::io(&toolchain.rustc))?;
+    if !output.status.success() {
+        return Err(FreightError::Tool {
+            program: "rustc".into(),
+            message: "could not get the version".into(),
+        });
+    }
+    RustcVersion::parse(&String::from_utf8_lossy(&output.stdout)).map_err(|message| {
+        FreightError::Tool {
+            program: "rustc".into(),
+            message,
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Edition {
     E2015,
     E2018,
     E2021,
+    E2024,
+}
+
+impl Edition {
+    /// Every edition Freight knows about, oldest first
+    pub const ALL: &'static [Edition] = &[Self::E2015, Self::E2018, Self::E2021, Self::E2024];
+
+    /// The first stable rustc with the edition
+    pub fn min_rustc(self) -> Version {
+        match self {
+            Self::E2015 => Version::new(1, 0, 0),
+            Self::E2018 => Version::new(1, 31, 0),
+            Self::E2021 => Version::new(1, 56, 0),
+            Self::E2024 => Version::new(1, 85, 0),
+        }
+    }
+
+    /// Whether `rustc` can compile the edition
+    pub fn supported_by(self, rustc: &RustcVersion) -> bool {
+        rustc.at_least(&self.min_rustc())
+    }
+
+    /// The newest edition `rustc` can compile, which new packages default to
+    pub fn latest(rustc: &RustcVersion) -> Self {
+        Self::ALL
+            .iter()
+            .rev()
+            .copied()
+            .find(|edition| edition.supported_by(rustc))
+            .unwrap_or(Self::E2015)
+    }
 }
 
 impl Display for Edition {
@@ -148,19 +515,21 @@ impl Display for Edition {
             Self::E2015 => "2015",
             Self::E2018 => "2018",
             Self::E2021 => "2021",
+            Self::E2024 => "2024",
         };
         write!(f, "{edition}")
     }
 }
 
 impl FromStr for Edition {
-    type Err = BoxError;
-    fn from_str(input: &str) -> Result<Self> {
+    type Err = String;
+    fn from_str(input: &str) -> std::result::Result<Self, String> {
         match input {
             "2015" => Ok(Self::E2015),
             "2018" => Ok(Self::E2018),
             "2021" => Ok(Self::E2021),
-            edition => Err(format!("Edition {edition} is not supported").into()),
+            "2024" => Ok(Self::E2024),
+            edition => Err(format!("Edition {edition} is not supported")),
         }
     }
 }
@@ -192,8 +561,8 @@ impl Display for CrateType {
 }
 
 impl FromStr for CrateType {
-    type Err = BoxError;
-    fn from_str(input: &str) -> Result<Self> {
+    type Err = String;
+    fn from_str(input: &str) -> std::result::Result<Self, String> {
         match input {
             "bin" => Ok(Self::Bin),
             "lib" => Ok(Self::Lib),
@@ -202,19 +571,21 @@ impl FromStr for CrateType {
             "cdylib" => Ok(Self::CDyLib),
             "staticlib" => Ok(Self::StaticLib),
             "proc-macro" => Ok(Self::ProcMacro),
-            crate_type => Err(format!("Crate Type {crate_type} is not supported").into()),
+            crate_type => Err(format!("Crate Type {crate_type} is not supported")),
         }
     }
 }
 
 #[test]
-fn edition_from_str() -> Result<()> {
+fn edition_from_str() -> std::result::Result<(), String> {
     let e2015 = Edition::from_str("2015")?;
     assert_eq!(e2015, Edition::E2015);
     let e2018 = Edition::from_str("2018")?;
     assert_eq!(e2018, Edition::E2018);
     let e2021 = Edition::from_str("2021")?;
     assert_eq!(e2021, Edition::E2021);
+    let e2024 = Edition::from_str("2024")?;
+    assert_eq!(e2024, Edition::E2024);
     if !Edition::from_str("\"2015\"").is_err() {
         panic!("bad string parsed correctly");
     }
@@ -223,7 +594,41 @@ fn edition_from_str() -> Result<()> {
 }
 
 #[test]
-fn crate_type_from_str() -> Result<()> {
+fn rustc_version() -> std::result::Result<(), String> {
+    let nightly = RustcVersion::parse(
+        "rustc 1.85.0-nightly (4d91de4e4 2025-02-17)\n\
+         binary: rustc\n\
+         commit-hash: 4d91de4e48198da2e33413efdcd9cd2cc0c46688\n\
+         commit-date: 2025-02-17\n\
+         host: aarch64-apple-darwin\n\
+         release: 1.85.0-nightly\n\
+         LLVM version: 19.1.7\n",
+    )?;
+    assert_eq!(nightly.version.pre, "nightly");
+    assert_eq!(
+        nightly.commit_hash.as_deref(),
+        Some("4d91de4e48198da2e33413efdcd9cd2cc0c46688")
+    );
+    assert_eq!(nightly.host, "aarch64-apple-darwin");
+    assert_eq!(nightly.llvm_version.as_deref(), Some("19.1.7"));
+    assert!(nightly.at_least(&Version::new(1, 85, 0)));
+    assert_eq!(Edition::latest(&nightly), Edition::E2024);
+
+    // Toolchains built from source may not know their commit
+    let old = RustcVersion::parse(
+        "rustc 1.80.1\ncommit-hash: unknown\nhost: x86_64-unknown-linux-gnu\nrelease: 1.80.1\n",
+    )?;
+    assert_eq!(old.commit_hash, None);
+    assert_eq!(old.llvm_version, None);
+    assert!(Edition::E2021.supported_by(&old));
+    assert!(!Edition::E2024.supported_by(&old));
+    assert_eq!(Edition::latest(&old), Edition::E2021);
+    assert!(RustcVersion::parse("rustc 1.80.1\n").is_err());
+    Ok(())
+}
+
+#[test]
+fn crate_type_from_str() -> std::result::Result<(), String> {
     let bin = CrateType::from_str("bin")?;
     assert_eq!(bin, CrateType::Bin);
     let lib = CrateType::from_str("lib")?;
@@ -1,5 +1,6 @@
-use super::BoxError;
+use super::FreightError;
 use super::Result;
+use crate::rustc::CrateType;
 use crate::rustc::Edition;
 use std::path::Path;
 use std::path::PathBuf;
@@ -11,6 +12,12 @@ pub struct RustDoc {
     crate_name: String,
     lib_path: PathBuf,
     out_path: Option<PathBuf>,
+    crate_type: Option<CrateType>,
+    externs: Vec<String>,
+    color: Option<bool>,
+    /// Extra flags from `RUSTDOCFLAGS` and the config, passed last
+    flags: Vec<String>,
+    program: PathBuf,
 }
 
 impl RustDoc {
@@ -25,14 +32,50 @@ impl RustDoc {
             crate_name: crate_name.into(),
             lib_path: lib_path.into(),
             out_path: out_path.map(|path| path.into()),
+            crate_type: None,
+            externs: Vec::new(),
+            color: None,
+            flags: Vec::new(),
+            program: "rustdoc".into(),
         }
     }
+    /// The rustdoc to run instead of the one on the `PATH`
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+    /// Only needed when documenting crates that aren't a plain library, like
+    /// proc macros
+    pub fn crate_type(mut self, crate_type: CrateType) -> Self {
+        self.crate_type = Some(crate_type);
+        self
+    }
+    pub fn externs(mut self, r#extern: impl Into<String>) -> Self {
+        self.externs.push(r#extern.into());
+        self
+    }
+    /// Whether rustdoc colors its output instead of deciding for itself
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = Some(color);
+        self
+    }
+    pub fn flags(mut self, flags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.flags.extend(flags.into_iter().map(Into::into));
+        self
+    }
     pub fn doc(&self, path: impl AsRef<Path>) -> Result<ExitStatus> {
-        let output = self.out_path.as_ref().ok_or_else(|| {
-            BoxError::from("The output path should be specified. This is a freight bug.")
-        })?;
+        let mut command = self.doc_command(path);
+        command.status().map_err(FreightError::io(&self.program))
+    }
+    /// The command [`RustDoc::doc`] runs
+    pub fn doc_command(&self, path: impl AsRef<Path>) -> Command {
+        let output = self
+            .out_path
+            .as_ref()
+            .expect("The output path should be specified. This is a freight bug.");
         let path = path.as_ref();
-        let exit_status = Command::new("rustdoc")
+        let mut command = Command::new(&self.program);
+        command
             .arg(path)
             .arg("--crate-name")
             .arg(&self.crate_name)
@@ -42,13 +85,18 @@ impl RustDoc {
             .arg(&self.lib_path)
             .arg("--out-dir")
             .arg(output)
-            .spawn()?
-            .wait()?;
-        Ok(exit_status)
+            .args(self.args());
+        command
     }
     pub fn test(&self, path: impl AsRef<Path>) -> Result<ExitStatus> {
+        let mut command = self.test_command(path);
+        command.status().map_err(FreightError::io(&self.program))
+    }
+    /// The command [`RustDoc::test`] runs
+    pub fn test_command(&self, path: impl AsRef<Path>) -> Command {
         let path = path.as_ref();
-        let exit_status = Command::new("rustdoc")
+        let mut command = Command::new(&self.program);
+        command
             .arg("--test")
             .arg(path)
             .arg("--crate-name")
@@ -57,8 +105,24 @@ impl RustDoc {
             .arg(self.edition.to_string())
             .arg("-L")
             .arg(&self.lib_path)
-            .spawn()?
-            .wait()?;
-        Ok(exit_status)
+            .args(self.args());
+        command
+    }
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(crate_type) = self.crate_type {
+            args.push("--crate-type".into());
+            args.push(crate_type.to_string());
+        }
+        for r#extern in &self.externs {
+            args.push("--extern".into());
+            args.push(r#extern.clone());
+        }
+        if let Some(color) = self.color {
+            args.push("--color".into());
+            args.push(if color { "always" } else { "never" }.into());
+        }
+        args.extend(self.flags.iter().cloned());
+        args
     }
 }
@@ -0,0 +1,264 @@
+//! Semantic versions and the requirements dependencies put on them, following
+//! the same rules as Cargo
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// The pre-release like `nightly` in `1.85.0-nightly`, empty for releases
+    pub pre: String,
+}
+
+impl Version {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            pre: String::new(),
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, String> {
+        let invalid =
+            || format!("`{input}` is not a valid version, expected something like `1.2.3`");
+        // Build metadata doesn't take part in comparisons
+        let version = input.split_once('+').map_or(input, |(version, _)| version);
+        let (numbers, pre) = version.split_once('-').unwrap_or((version, ""));
+        let mut numbers = numbers.split('.').map(number);
+        match (
+            numbers.next(),
+            numbers.next(),
+            numbers.next(),
+            numbers.next(),
+        ) {
+            (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => Ok(Self {
+                major,
+                minor,
+                patch,
+                pre: pre.to_string(),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            // A pre-release comes before its release
+            .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self.pre.cmp(&other.pre),
+            })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre)?;
+        }
+        Ok(())
+    }
+}
+
+/// A requirement like `^1.2` or `>=1.0, <2`. A version matches if it
+/// matches every comparator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    comparators: Vec<Comparator>,
+    input: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparator {
+    op: Op,
+    major: u64,
+    minor: Option<u64>,
+    patch: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Tilde,
+    Caret,
+    Wildcard,
+}
+
+impl VersionReq {
+    pub fn matches(&self, version: &Version) -> bool {
+        // Pre-releases only match when asked for explicitly
+        version.pre.is_empty() && self.comparators.iter().all(|c| c.matches(version))
+    }
+}
+
+impl Comparator {
+    fn matches(&self, version: &Version) -> bool {
+        let v = (version.major, version.minor, version.patch);
+        let lower = (self.major, self.minor.unwrap_or(0), self.patch.unwrap_or(0));
+        match self.op {
+            Op::Wildcard => true,
+            Op::Exact => {
+                version.major == self.major
+                    && self.minor.is_none_or(|minor| version.minor == minor)
+                    && self.patch.is_none_or(|patch| version.patch == patch)
+            }
+            Op::Greater => match (self.minor, self.patch) {
+                (None, _) => version.major > self.major,
+                (Some(minor), None) => (version.major, version.minor) > (self.major, minor),
+                (Some(_), Some(_)) => v > lower,
+            },
+            Op::GreaterEq => v >= lower,
+            Op::Less => v < lower,
+            Op::LessEq => match (self.minor, self.patch) {
+                (None, _) => version.major <= self.major,
+                (Some(minor), None) => (version.major, version.minor) <= (self.major, minor),
+                (Some(_), Some(_)) => v <= lower,
+            },
+            Op::Tilde => {
+                v >= lower
+                    && version.major == self.major
+                    && self.minor.is_none_or(|minor| version.minor == minor)
+            }
+            Op::Caret => {
+                if v < lower {
+                    false
+                } else if self.major > 0 || self.minor.is_none() {
+                    version.major == self.major
+                } else if self.minor > Some(0) || self.patch.is_none() {
+                    version.major == 0 && Some(version.minor) == self.minor
+                } else {
+                    v == lower
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!("`{input}` is not a valid version requirement, expected something like `1.2` or `>=1.0, <2`")
+        };
+        let mut comparators = Vec::new();
+        for comparator in input.split(',') {
+            let comparator = comparator.trim();
+            if comparator == "*" {
+                comparators.push(Comparator {
+                    op: Op::Wildcard,
+                    major: 0,
+                    minor: None,
+                    patch: None,
+                });
+                continue;
+            }
+            let (op, version) = [
+                (">=", Op::GreaterEq),
+                ("<=", Op::LessEq),
+                (">", Op::Greater),
+                ("<", Op::Less),
+                ("=", Op::Exact),
+                ("~", Op::Tilde),
+                ("^", Op::Caret),
+            ]
+            .into_iter()
+            .find_map(|(prefix, op)| Some((op, comparator.strip_prefix(prefix)?)))
+            .unwrap_or((Op::Caret, comparator));
+            let mut parts = version.trim().split('.');
+            let major = parts.next().and_then(number).ok_or_else(invalid)?;
+            let mut part = || match parts.next() {
+                None => Ok(None),
+                Some("*") | Some("x") | Some("X") if op == Op::Caret || op == Op::Exact => Ok(None),
+                Some(part) => number(part).map(Some).ok_or_else(invalid),
+            };
+            let minor = part()?;
+            let patch = part()?;
+            if parts.next().is_some() || (minor.is_none() && patch.is_some()) {
+                return Err(invalid());
+            }
+            // `1.*` is the same as `1`, which means `=1` rather than `^1`
+            let op = if op == Op::Caret && version.contains('*') {
+                Op::Exact
+            } else {
+                op
+            };
+            comparators.push(Comparator {
+                op,
+                major,
+                minor,
+                patch,
+            });
+        }
+        Ok(Self {
+            comparators,
+            input: input.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.input)
+    }
+}
+
+fn number(part: &str) -> Option<u64> {
+    // Leading zeroes aren't allowed, like Cargo
+    if part.is_empty() || (part.len() > 1 && part.starts_with('0')) {
+        return None;
+    }
+    part.parse().ok()
+}
+
+#[test]
+fn versions_and_requirements() -> Result<(), String> {
+    let version: Version = "1.85.0-nightly".parse()?;
+    assert_eq!(version.pre, "nightly");
+    assert!(version < Version::new(1, 85, 0));
+    assert!(Version::new(1, 80, 1) < version);
+    assert_eq!("1.2.3+build".parse::<Version>()?, Version::new(1, 2, 3));
+    assert!("1.2".parse::<Version>().is_err());
+    assert!("01.2.3".parse::<Version>().is_err());
+
+    let matches = |req: &str, version: &str| -> Result<bool, String> {
+        Ok(req.parse::<VersionReq>()?.matches(&version.parse()?))
+    };
+    assert!(matches("1.2", "1.9.0")?);
+    assert!(!matches("1.2", "2.0.0")?);
+    assert!(!matches("^0.2.3", "0.3.0")?);
+    assert!(matches("~1.2", "1.2.7")?);
+    assert!(!matches("~1.2", "1.3.0")?);
+    assert!(matches(">=1.0, <2", "1.5.0")?);
+    assert!(!matches(">=1.0, <2", "2.0.0")?);
+    assert!(matches("1.*", "1.4.0")?);
+    assert!(matches("*", "0.0.1")?);
+    assert!(matches("=0.1.0", "0.1.0")?);
+    assert!(!matches("1", "1.0.0-beta")?);
+    assert!("abc".parse::<VersionReq>().is_err());
+    assert!(">= 1.x.2".parse::<VersionReq>().is_err());
+    Ok(())
+}
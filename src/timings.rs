@@ -0,0 +1,414 @@
+//! The report written with `--timings`. It records when each unit was ready
+//! to start, when rustc started on it, when its metadata was ready and when it
+//! finished, along with how many units were running over time. The report is
+//! written to the `freight-timings` folder of the target directory as HTML
+//! with a Gantt chart and as JSON with the same data.
+use super::FreightError;
+use super::Result;
+use crate::job_queue::Job;
+use crate::json::Value;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+use std::time::SystemTime;
+
+/// When things happened to a unit, in seconds since the build started
+struct UnitTiming {
+    /// Like `` `foo` (lib) ``
+    name: String,
+    package_id: String,
+    target: String,
+    /// The kind of target, like `lib`, `bin` or `test`
+    kind: Vec<String>,
+    /// Its dependencies were ready, though it may wait for a free job slot
+    queued: Option<f64>,
+    started: Option<f64>,
+    /// The metadata was written, which is when dependents can start if it's
+    /// pipelined
+    rmeta: Option<f64>,
+    finished: Option<f64>,
+    fresh: bool,
+    /// The outputs were restored from the compile cache
+    cached: bool,
+}
+
+impl UnitTiming {
+    fn duration(&self) -> f64 {
+        match (self.started, self.finished) {
+            (Some(started), Some(finished)) => finished - started,
+            _ => 0.0,
+        }
+    }
+}
+
+/// How many units were in each state from `time` until the next sample
+struct Concurrency {
+    time: f64,
+    /// rustc is running for them
+    active: usize,
+    /// Ready but waiting for a free job slot
+    waiting: usize,
+    /// Waiting for their dependencies
+    inactive: usize,
+}
+
+pub struct Timings {
+    start: Instant,
+    /// When the build started, for naming the report
+    started_at: SystemTime,
+    parallelism: usize,
+    units: Vec<UnitTiming>,
+    concurrency: Vec<Concurrency>,
+}
+
+impl Timings {
+    pub fn new(jobs: &[Job], parallelism: usize) -> Self {
+        let units = jobs
+            .iter()
+            .map(|job| UnitTiming {
+                name: job.name.clone(),
+                package_id: job.artifact.package_id.clone(),
+                target: job.artifact.name.clone(),
+                kind: job.artifact.kind.clone(),
+                queued: None,
+                started: None,
+                rmeta: None,
+                finished: None,
+                fresh: false,
+                cached: false,
+            })
+            .collect();
+        Self {
+            start: Instant::now(),
+            started_at: SystemTime::now(),
+            parallelism,
+            units,
+            concurrency: Vec::new(),
+        }
+    }
+
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    /// The unit's dependencies are ready. Only the first call counts.
+    pub fn queued(&mut self, index: usize) {
+        let now = self.now();
+        self.units[index].queued.get_or_insert(now);
+    }
+    pub fn started(&mut self, index: usize) {
+        self.units[index].started = Some(self.now());
+    }
+    pub fn fresh(&mut self, index: usize) {
+        let now = self.now();
+        let unit = &mut self.units[index];
+        unit.started = Some(now);
+        unit.finished = Some(now);
+        unit.fresh = true;
+    }
+    pub fn metadata(&mut self, index: usize) {
+        self.units[index].rmeta = Some(self.now());
+    }
+    pub fn finished(&mut self, index: usize, cached: bool) {
+        self.units[index].finished = Some(self.now());
+        self.units[index].cached = cached;
+    }
+    /// Record how many units are in each state, if that changed
+    pub fn concurrency(&mut self, active: usize, waiting: usize, inactive: usize) {
+        if let Some(last) = self.concurrency.last() {
+            if (last.active, last.waiting, last.inactive) == (active, waiting, inactive) {
+                return;
+            }
+        }
+        let time = self.now();
+        self.concurrency.push(Concurrency {
+            time,
+            active,
+            waiting,
+            inactive,
+        });
+    }
+
+    /// Write the HTML and JSON reports into `dir`, returning the path of the
+    /// HTML one
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir).map_err(FreightError::io(dir))?;
+        let stamp = timestamp(self.started_at);
+        // Builds in the same second get a counter like Cargo gives them
+        let name = (0..)
+            .map(|count| match count {
+                0 => format!("freight-timing-{stamp}"),
+                count => format!("freight-timing-{stamp}-{count}"),
+            })
+            .find(|name| {
+                !dir.join(format!("{name}.html")).exists()
+                    && !dir.join(format!("{name}.json")).exists()
+            })
+            .expect("a name that isn't taken");
+        let total = self.now();
+        let html = dir.join(format!("{name}.html"));
+        fs::write(&html, self.html(total)).map_err(FreightError::io(&html))?;
+        let json = dir.join(format!("{name}.json"));
+        fs::write(&json, format!("{}\n", self.json(total))).map_err(FreightError::io(&json))?;
+        Ok(html)
+    }
+
+    fn json(&self, total: f64) -> Value {
+        let seconds = |time: Option<f64>| time.map(Value::Number).unwrap_or(Value::Null);
+        let units = self
+            .units
+            .iter()
+            .map(|unit| {
+                Value::from([
+                    ("name", unit.name.as_str().into()),
+                    ("package_id", unit.package_id.as_str().into()),
+                    ("target", unit.target.as_str().into()),
+                    ("kind", unit.kind.clone().into()),
+                    ("fresh", unit.fresh.into()),
+                    ("cached", unit.cached.into()),
+                    ("queued", seconds(unit.queued)),
+                    ("start", seconds(unit.started)),
+                    ("rmeta", seconds(unit.rmeta)),
+                    ("end", seconds(unit.finished)),
+                    ("duration", Value::Number(unit.duration())),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let concurrency = self
+            .concurrency
+            .iter()
+            .map(|sample| {
+                Value::from([
+                    ("time", Value::Number(sample.time)),
+                    ("active", Value::Number(sample.active as f64)),
+                    ("waiting", Value::Number(sample.waiting as f64)),
+                    ("inactive", Value::Number(sample.inactive as f64)),
+                ])
+            })
+            .collect::<Vec<_>>();
+        Value::from([
+            ("timestamp", timestamp(self.started_at).into()),
+            ("duration", Value::Number(total)),
+            ("jobs", Value::Number(self.parallelism as f64)),
+            ("units", Value::Array(units)),
+            ("concurrency", Value::Array(concurrency)),
+        ])
+    }
+
+    fn html(&self, total: f64) -> String {
+        const WIDTH: f64 = 1000.0;
+        const ROW: f64 = 20.0;
+        const LABEL: f64 = 240.0;
+        let scale = WIDTH / total.max(0.01);
+        let built = self
+            .units
+            .iter()
+            .filter(|unit| !unit.fresh && unit.started.is_some())
+            .collect::<Vec<_>>();
+        let fresh = self.units.iter().filter(|unit| unit.fresh).count();
+        let max_active = self
+            .concurrency
+            .iter()
+            .map(|sample| sample.active)
+            .max()
+            .unwrap_or(0);
+
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>Freight build timings</title>\n<style>\n");
+        html.push_str("body { font-family: sans-serif; margin: 2em; }\n");
+        html.push_str("table { border-collapse: collapse; }\n");
+        html.push_str("td, th { padding: 2px 10px; text-align: left; }\n");
+        html.push_str("tr:nth-child(even) { background: #f2f2f2; }\n");
+        html.push_str("svg text { font-size: 12px; }\n");
+        html.push_str("</style>\n</head>\n<body>\n<h1>Freight build timings</h1>\n");
+        let _ = writeln!(
+            html,
+            "<table>\n<tr><th>Started</th><td>{}</td></tr>\n<tr><th>Total time</th><td>{total:.2}s</td></tr>\n<tr><th>Units</th><td>{} compiled, {fresh} fresh</td></tr>\n<tr><th>Jobs</th><td>{}</td></tr>\n<tr><th>Max concurrency</th><td>{max_active}</td></tr>\n</table>",
+            timestamp(self.started_at),
+            built.len(),
+            self.parallelism,
+        );
+
+        // One row per unit: waiting for a job slot in grey, before the
+        // metadata was ready in light blue and the rest in dark blue
+        html.push_str("<h2>Units</h2>\n");
+        let height = ROW * built.len() as f64 + ROW;
+        let _ = writeln!(
+            html,
+            "<svg width=\"{}\" height=\"{height}\">",
+            LABEL + WIDTH + 60.0
+        );
+        for (row, unit) in built.iter().enumerate() {
+            let y = ROW * row as f64;
+            let started = unit.started.unwrap_or(0.0);
+            let finished = unit.finished.unwrap_or(total);
+            let x = |time: f64| LABEL + time * scale;
+            let _ = writeln!(
+                html,
+                "<text x=\"0\" y=\"{}\">{}</text>",
+                y + 14.0,
+                escape(&unit.name)
+            );
+            if let Some(queued) = unit.queued.filter(|queued| *queued < started) {
+                let _ = writeln!(
+                    html,
+                    "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"4\" fill=\"#bbbbbb\"/>",
+                    x(queued),
+                    y + 8.0,
+                    (started - queued) * scale
+                );
+            }
+            let rmeta = unit.rmeta.unwrap_or(started).clamp(started, finished);
+            let _ = writeln!(
+                html,
+                "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"16\" fill=\"#95cce8\"/>",
+                x(started),
+                y + 2.0,
+                (rmeta - started) * scale
+            );
+            let _ = writeln!(
+                html,
+                "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"16\" fill=\"#3b6fb6\"><title>{} {:.2}s</title></rect>",
+                x(rmeta),
+                y + 2.0,
+                ((finished - rmeta) * scale).max(1.0),
+                escape(&unit.name),
+                unit.duration()
+            );
+            let _ = writeln!(
+                html,
+                "<text x=\"{:.1}\" y=\"{}\">{:.2}s</text>",
+                x(finished) + 4.0,
+                y + 14.0,
+                unit.duration()
+            );
+        }
+        html.push_str("</svg>\n");
+
+        // How many units were running, waiting for a job slot and waiting for
+        // their dependencies over time, as steps
+        html.push_str("<h2>Concurrency</h2>\n");
+        let most = self
+            .concurrency
+            .iter()
+            .map(|sample| sample.active.max(sample.waiting).max(sample.inactive))
+            .max()
+            .unwrap_or(0)
+            .max(1) as f64;
+        const HEIGHT: f64 = 200.0;
+        let _ = writeln!(
+            html,
+            "<svg width=\"{}\" height=\"{}\">",
+            LABEL + WIDTH + 60.0,
+            HEIGHT + 10.0
+        );
+        let counts = |count: fn(&Concurrency) -> usize| {
+            self.concurrency
+                .iter()
+                .map(|sample| (sample.time, count(sample)))
+                .collect::<Vec<_>>()
+        };
+        let lines = [
+            ("#3b6fb6", counts(|sample| sample.active)),
+            ("#e0a030", counts(|sample| sample.waiting)),
+            ("#bbbbbb", counts(|sample| sample.inactive)),
+        ];
+        for (color, counts) in lines {
+            let mut points = String::new();
+            let mut last = None;
+            for (time, count) in counts {
+                let x = LABEL + time * scale;
+                let y = 5.0 + HEIGHT - count as f64 / most * HEIGHT;
+                if let Some(last) = last {
+                    let _ = write!(points, "{x:.1},{last:.1} ");
+                }
+                let _ = write!(points, "{x:.1},{y:.1} ");
+                last = Some(y);
+            }
+            let _ = writeln!(
+                html,
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"2\"/>",
+                points.trim_end()
+            );
+        }
+        let _ = writeln!(
+            html,
+            "<text x=\"0\" y=\"20\" fill=\"#3b6fb6\">running</text>\n<text x=\"0\" y=\"40\" fill=\"#e0a030\">waiting for a job</text>\n<text x=\"0\" y=\"60\" fill=\"#999999\">waiting for dependencies</text>\n<text x=\"{}\" y=\"15\">{most}</text>",
+            LABEL + WIDTH + 4.0
+        );
+        html.push_str("</svg>\n");
+
+        // The slowest units first
+        html.push_str("<h2>Slowest units</h2>\n<table>\n");
+        html.push_str("<tr><th>Unit</th><th>Total</th><th>Codegen</th><th>Waiting</th></tr>\n");
+        let mut slowest = built;
+        slowest.sort_by(|a, b| b.duration().total_cmp(&a.duration()));
+        for unit in slowest {
+            let codegen = match (unit.rmeta, unit.finished) {
+                (Some(rmeta), Some(finished)) => format!("{:.2}s", finished - rmeta),
+                _ => String::new(),
+            };
+            let waiting = match (unit.queued, unit.started) {
+                (Some(queued), Some(started)) => started - queued,
+                _ => 0.0,
+            };
+            let _ = writeln!(
+                html,
+                "<tr><td>{}{}</td><td>{:.2}s</td><td>{codegen}</td><td>{waiting:.2}s</td></tr>",
+                escape(&unit.name),
+                if unit.cached { " (cached)" } else { "" },
+                unit.duration()
+            );
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The time in UTC like `20240131T235959Z`, which sorts the reports by when
+/// they were made
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, rest) = (seconds / 86400, seconds % 86400);
+    // Days to a date in the proleptic Gregorian calendar, counting in eras of
+    // 400 years that start on the 1st of March
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+#[test]
+fn timestamps() {
+    use std::time::Duration;
+    let at = |seconds| timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+    assert_eq!(at(0), "19700101T000000Z");
+    assert_eq!(at(951782400), "20000229T000000Z");
+    assert_eq!(at(1706745599), "20240131T235959Z");
+    assert_eq!(at(1709164800), "20240229T000000Z");
+}
@@ -0,0 +1,532 @@
+//! A small TOML parser covering the subset of the format that Freight reads:
+//! tables, dotted keys, strings, integers, booleans, arrays and inline tables.
+use crate::error::Span;
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub type Table = BTreeMap<String, Value>;
+
+/// Where each key and its value are in the document, by dotted key like
+/// `dependencies.foo.path`, so errors about them can point at the source
+#[derive(Debug, Default)]
+pub struct Spans(BTreeMap<String, (Span, Span)>);
+
+impl Spans {
+    pub fn key(&self, key: &str) -> Option<Span> {
+        self.0.get(key).map(|(key, _)| *key)
+    }
+
+    pub fn value(&self, key: &str) -> Option<Span> {
+        self.0.get(key).map(|(_, value)| *value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn type_str(&self) -> &'static str {
+        match self {
+            Self::String(_) => "string",
+            Self::Integer(_) => "integer",
+            Self::Boolean(_) => "boolean",
+            Self::Array(_) => "array",
+            Self::Table(_) => "table",
+        }
+    }
+}
+
+/// Values are shown the way they'd be written in a document, with tables
+/// inline
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(s) => write!(f, "{s:?}"),
+            Self::Integer(i) => write!(f, "{i}"),
+            Self::Boolean(b) => write!(f, "{b}"),
+            Self::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Self::Table(table) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in table.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {key} = {value}")?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+/// Why a document isn't valid TOML and where
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "TOML parse error on line {}: {}",
+            self.span.line, self.message
+        )
+    }
+}
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+/// Parse a document and note where its keys and values are
+pub fn parse(input: &str) -> Result<(Table, Spans)> {
+    let mut parser = Parser {
+        input,
+        pos: 0,
+        spans: Spans::default(),
+    };
+    let table = parser.document()?;
+    Ok((table, parser.spans))
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    spans: Spans,
+}
+
+impl Parser<'_> {
+    fn document(&mut self) -> Result<Table> {
+        let mut root = Table::new();
+        let mut headers: Vec<Vec<String>> = Vec::new();
+        let mut current = Vec::new();
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    self.bump();
+                    if self.peek() == Some('[') {
+                        return Err(self.error("arrays of tables are not supported"));
+                    }
+                    self.skip_whitespace();
+                    let start = self.pos;
+                    let path = self.key()?;
+                    let span = self.span(start);
+                    self.record(&[], &path, span, span);
+                    self.skip_whitespace();
+                    self.expect(']')?;
+                    if headers.contains(&path) {
+                        return Err(self.error(format!(
+                            "table `{}` is defined more than once",
+                            path.join(".")
+                        )));
+                    }
+                    table_at(&mut root, &path).map_err(|e| self.error(e))?;
+                    headers.push(path.clone());
+                    current = path;
+                }
+                Some(_) => {
+                    let start = self.pos;
+                    let key = self.key()?;
+                    let key_span = self.span(start);
+                    self.skip_whitespace();
+                    self.expect('=')?;
+                    self.skip_whitespace();
+                    let start = self.pos;
+                    let path = current.iter().chain(&key).cloned().collect::<Vec<_>>();
+                    let value = self.value(&path)?;
+                    self.record(&current, &key, key_span, self.span(start));
+                    let table = table_at(&mut root, &current).map_err(|e| self.error(e))?;
+                    insert(table, &key, value).map_err(|e| self.error(e))?;
+                }
+            }
+            self.end_of_line()?;
+        }
+        Ok(root)
+    }
+
+    /// Note the spans of a key and its value, with the key relative to the
+    /// table at `prefix`
+    fn record(&mut self, prefix: &[String], key: &[String], key_span: Span, value_span: Span) {
+        let path = prefix.iter().chain(key).cloned().collect::<Vec<_>>();
+        self.spans.0.insert(path.join("."), (key_span, value_span));
+    }
+
+    fn key(&mut self) -> Result<Vec<String>> {
+        let mut path = vec![self.simple_key()?];
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('.') {
+                return Ok(path);
+            }
+            self.bump();
+            self.skip_whitespace();
+            path.push(self.simple_key()?);
+        }
+    }
+
+    fn simple_key(&mut self) -> Result<String> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                {
+                    self.bump();
+                }
+                if start == self.pos {
+                    Err(self.error("expected a key"))
+                } else {
+                    Ok(self.input[start..self.pos].to_string())
+                }
+            }
+        }
+    }
+
+    /// Parse a value, with `key` being where it's going so that the keys of
+    /// inline tables can be recorded
+    fn value(&mut self, key: &[String]) -> Result<Value> {
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.basic_string()?)),
+            Some('\'') => Ok(Value::String(self.literal_string()?)),
+            Some('[') => self.array(key),
+            Some('{') => self.inline_table(key),
+            Some('t') | Some('f') => {
+                if self.eat("true") {
+                    Ok(Value::Boolean(true))
+                } else if self.eat("false") {
+                    Ok(Value::Boolean(false))
+                } else {
+                    Err(self.error("expected a value"))
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => self.integer(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        if self.eat("\"\"") {
+            return Err(self.error("multi-line strings are not supported"));
+        }
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(string),
+                Some('\\') => match self.bump() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        self.expect('\'')?;
+        let start = self.pos;
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some('\'') => return Ok(self.input[start..self.pos - 1].to_string()),
+                Some(_) => {}
+            }
+        }
+    }
+
+    fn integer(&mut self) -> Result<Value> {
+        let start = self.pos;
+        if matches!(self.peek(), Some('-') | Some('+')) {
+            self.bump();
+        }
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || c == '_') {
+            self.bump();
+        }
+        self.input[start..self.pos]
+            .replace('_', "")
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| self.error("invalid integer"))
+    }
+
+    fn array(&mut self, key: &[String]) -> Result<Value> {
+        self.expect('[')?;
+        let mut array = Vec::new();
+        loop {
+            self.skip_trivia();
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::Array(array));
+            }
+            array.push(self.value(key)?);
+            self.skip_trivia();
+            match self.bump() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(array)),
+                _ => return Err(self.error("expected `,` or `]` in array")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self, prefix: &[String]) -> Result<Value> {
+        self.expect('{')?;
+        let mut table = Table::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(Value::Table(table));
+        }
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let key = self.key()?;
+            let key_span = self.span(start);
+            self.skip_whitespace();
+            self.expect('=')?;
+            self.skip_whitespace();
+            let start = self.pos;
+            let path = prefix.iter().chain(&key).cloned().collect::<Vec<_>>();
+            let value = self.value(&path)?;
+            self.record(prefix, &key, key_span, self.span(start));
+            insert(&mut table, &key, value).map_err(|e| self.error(e))?;
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Table(table)),
+                _ => return Err(self.error("expected `,` or `}` in inline table")),
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() == Some('#') {
+            self.skip_comment();
+        }
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some('\r') if self.input[self.pos..].starts_with("\r\n") => Ok(()),
+            Some(_) => Err(self.error("expected a newline after value")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.bump();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.bump();
+        }
+    }
+
+    /// Skip whitespace, newlines and comments
+    fn skip_trivia(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ') | Some('\t') | Some('\r') | Some('\n') => {
+                    self.bump();
+                }
+                Some('#') => self.skip_comment(),
+                _ => return,
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.input[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.peek() == Some(c) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{c}`")))
+        }
+    }
+
+    /// The span from `start` up to where the parser is, cut off at the end of
+    /// the first line
+    fn span(&self, start: usize) -> Span {
+        let line_start = self.input[..start].rfind('\n').map_or(0, |i| i + 1);
+        let text = &self.input[start..self.pos];
+        let text = text.split('\n').next().unwrap_or(text).trim_end();
+        Span {
+            line: self.input[..start].matches('\n').count() + 1,
+            column: self.input[line_start..start].chars().count() + 1,
+            len: text.chars().count().max(1),
+        }
+    }
+
+    fn error(&self, msg: impl fmt::Display) -> ParseError {
+        ParseError {
+            message: msg.to_string(),
+            span: self.span(self.pos),
+        }
+    }
+}
+
+/// Walk down to the table at `path`, creating any tables that don't exist yet
+fn table_at<'t>(
+    root: &'t mut Table,
+    path: &[String],
+) -> std::result::Result<&'t mut Table, String> {
+    let mut table = root;
+    for key in path {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match entry {
+            Value::Table(table) => table,
+            value => {
+                return Err(format!(
+                    "key `{key}` is a {} and not a table",
+                    value.type_str()
+                ))
+            }
+        };
+    }
+    Ok(table)
+}
+
+fn insert(table: &mut Table, key: &[String], value: Value) -> std::result::Result<(), String> {
+    let (last, parents) = key.split_last().expect("keys are never empty");
+    let table = table_at(table, parents)?;
+    if table.contains_key(last) {
+        return Err(format!("duplicate key `{}`", key.join(".")));
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+#[test]
+fn parse_tables_and_values() -> Result<()> {
+    let (table, _) = parse(
+        "name = \"freight\" # a comment\n\
+         \n\
+         [build]\n\
+         target = [\n  \"wasm32-unknown-unknown\",\n  'x86_64-unknown-none',\n]\n\
+         jobs = 4\n\
+         [dependencies]\n\
+         foo = { path = \"../foo\", optional = false }\n",
+    )?;
+    assert_eq!(table["name"], Value::String("freight".into()));
+    let Value::Table(build) = &table["build"] else {
+        panic!("build is not a table");
+    };
+    assert_eq!(
+        build["target"],
+        Value::Array(vec![
+            Value::String("wasm32-unknown-unknown".into()),
+            Value::String("x86_64-unknown-none".into()),
+        ])
+    );
+    assert_eq!(build["jobs"], Value::Integer(4));
+    let Value::Table(deps) = &table["dependencies"] else {
+        panic!("dependencies is not a table");
+    };
+    let Value::Table(foo) = &deps["foo"] else {
+        panic!("foo is not a table");
+    };
+    assert_eq!(foo["path"], Value::String("../foo".into()));
+    assert_eq!(foo["optional"], Value::Boolean(false));
+    Ok(())
+}
+
+#[test]
+fn parse_records_spans() -> Result<()> {
+    let (_, spans) = parse(
+        "name = \"freight\"\n\
+         [dependencies]\n\
+         foo = { path = \"../foo\" }\n",
+    )?;
+    assert_eq!(
+        spans.value("name"),
+        Some(Span {
+            line: 1,
+            column: 8,
+            len: 9
+        })
+    );
+    assert_eq!(
+        spans.key("dependencies.foo"),
+        Some(Span {
+            line: 3,
+            column: 1,
+            len: 3
+        })
+    );
+    assert_eq!(
+        spans.value("dependencies.foo.path"),
+        Some(Span {
+            line: 3,
+            column: 16,
+            len: 8
+        })
+    );
+    assert_eq!(spans.key("missing"), None);
+    Ok(())
+}
+
+#[test]
+fn parse_rejects_duplicates() {
+    let error = parse("a = 1\na = 2\n").unwrap_err();
+    assert_eq!(error.message, "duplicate key `a`");
+    assert_eq!(
+        error.span,
+        Span {
+            line: 2,
+            column: 6,
+            len: 1
+        }
+    );
+    assert!(parse("[a]\n[a]\n").is_err());
+    assert!(parse("a = 1 b = 2\n").is_err());
+}
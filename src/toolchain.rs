@@ -0,0 +1,259 @@
+//! Picking the rustc and rustdoc to build with. In order of precedence these
+//! come from the `RUSTC` and `RUSTDOC` environment variables, the `[build]`
+//! table of `.freight/config.toml`, the toolchain named with `freight +name`
+//! or in `rust-toolchain.toml`, and finally whatever is on the `PATH`.
+use super::FreightError;
+use super::Result;
+use crate::config::Config;
+use crate::toml;
+use crate::toml::Value;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    pub rustc: PathBuf,
+    pub rustdoc: PathBuf,
+    /// A program compilations go through, like sccache, which is given the
+    /// path to rustc as its first argument
+    pub wrapper: Option<PathBuf>,
+}
+
+impl Default for Toolchain {
+    fn default() -> Self {
+        Self {
+            rustc: "rustc".into(),
+            rustdoc: "rustdoc".into(),
+            wrapper: None,
+        }
+    }
+}
+
+impl Toolchain {
+    /// Work out the toolchain for the project at `root`
+    pub fn resolve(root: &Path) -> Result<Self> {
+        let config = Config::load(root)?.build;
+        let dir = toolchain_dir(root)?;
+        let bin = |name: &str| dir.as_ref().map(|dir| dir.join("bin").join(name));
+        Ok(Self {
+            rustc: env_path("RUSTC")
+                .or(config.rustc)
+                .or_else(|| bin("rustc"))
+                .unwrap_or_else(|| "rustc".into()),
+            rustdoc: env_path("RUSTDOC")
+                .or(config.rustdoc)
+                .or_else(|| bin("rustdoc"))
+                .unwrap_or_else(|| "rustdoc".into()),
+            wrapper: match env::var_os("RUSTC_WRAPPER") {
+                // An empty wrapper turns off the one in the config
+                Some(wrapper) if wrapper.is_empty() => None,
+                Some(wrapper) => Some(wrapper.into()),
+                None => config.rustc_wrapper,
+            },
+        })
+    }
+
+    /// A command running rustc through the wrapper, if there is one, for
+    /// compiling. Queries like `--print` skip the wrapper.
+    pub fn rustc_command(&self) -> Command {
+        match &self.wrapper {
+            Some(wrapper) => {
+                let mut command = Command::new(wrapper);
+                command.arg(&self.rustc);
+                command
+            }
+            None => Command::new(&self.rustc),
+        }
+    }
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The directory of the toolchain picked with `freight +name`, which sets
+/// `RUSTUP_TOOLCHAIN` like rustup does, or in the project's toolchain file.
+/// Components the toolchain file asks for must be installed.
+fn toolchain_dir(root: &Path) -> Result<Option<PathBuf>> {
+    let file = ToolchainFile::load(root)?;
+    let (name, dir) = match (env::var("RUSTUP_TOOLCHAIN"), &file) {
+        (Ok(name), _) if !name.is_empty() => {
+            let dir = installed(&name)?;
+            (name, dir)
+        }
+        (
+            _,
+            Some(ToolchainFile {
+                path: Some(path), ..
+            }),
+        ) => {
+            let dir = root.join(path);
+            if !dir.join("bin").exists() {
+                return Err(FreightError::Toolchain(format!(
+                    "The toolchain at {} has no bin directory",
+                    dir.display()
+                )));
+            }
+            (path.display().to_string(), dir)
+        }
+        (
+            _,
+            Some(ToolchainFile {
+                channel: Some(channel),
+                ..
+            }),
+        ) => (channel.clone(), installed(channel)?),
+        _ => return Ok(None),
+    };
+
+    let components = file.map(|file| file.components).unwrap_or_default();
+    // rustup lists what it installed, toolchains it didn't install can't be
+    // checked
+    if let Ok(installed) = fs::read_to_string(dir.join("lib/rustlib/components")) {
+        for component in components {
+            let prefix = format!("{component}-");
+            if !installed
+                .lines()
+                .any(|line| line == component || line.starts_with(&prefix))
+            {
+                return Err(FreightError::Toolchain(format!(
+                    "Toolchain {name} is missing the {component} component, \
+                     install it with `rustup component add {component} --toolchain {name}`"
+                )));
+            }
+        }
+    }
+    Ok(Some(dir))
+}
+
+/// Find the rustup toolchain called `name`, which can leave off the host
+/// triple like `nightly` for `nightly-x86_64-unknown-linux-gnu`. Like rustup
+/// the host's toolchain is picked, and any other only when it's the only one.
+fn installed(name: &str) -> Result<PathBuf> {
+    let rustup_home = env::var_os("RUSTUP_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".rustup")));
+    let not_installed = || FreightError::Toolchain(format!("Toolchain {name} is not installed"));
+    let rustup_home = rustup_home.ok_or_else(not_installed)?;
+    let toolchains = rustup_home.join("toolchains");
+    if toolchains.join(name).is_dir() {
+        return Ok(toolchains.join(name));
+    }
+    let host = toolchains.join(format!("{name}-{}", host_triple(&rustup_home)));
+    if host.is_dir() {
+        return Ok(host);
+    }
+    let prefix = format!("{name}-");
+    let mut matches = fs::read_dir(&toolchains)
+        .map_err(|_| not_installed())?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|file| file.starts_with(&prefix))
+        .collect::<Vec<_>>();
+    matches.sort();
+    match matches.as_slice() {
+        [] => Err(not_installed()),
+        [only] => Ok(toolchains.join(only)),
+        _ => Err(FreightError::Toolchain(format!(
+            "Toolchain {name} is ambiguous, it could be any of {}",
+            matches.join(", ")
+        ))),
+    }
+}
+
+/// The triple rustup installs toolchains for by default, from its settings or
+/// else the machine Freight runs on
+fn host_triple(rustup_home: &Path) -> String {
+    let settings = fs::read_to_string(rustup_home.join("settings.toml")).ok();
+    if let Some((table, _)) = settings.and_then(|settings| toml::parse(&settings).ok()) {
+        if let Some(Value::String(host)) = table.get("default_host_triple") {
+            return host.clone();
+        }
+    }
+    let arch = env::consts::ARCH;
+    match env::consts::OS {
+        "linux" => format!("{arch}-unknown-linux-gnu"),
+        "macos" => format!("{arch}-apple-darwin"),
+        "windows" => format!("{arch}-pc-windows-msvc"),
+        os => format!("{arch}-unknown-{os}"),
+    }
+}
+
+/// `rust-toolchain.toml`, or the older `rust-toolchain` which can also be just
+/// the channel on its own
+#[derive(Debug, Default)]
+struct ToolchainFile {
+    channel: Option<String>,
+    /// A toolchain directory to use instead of a rustup channel
+    path: Option<PathBuf>,
+    components: Vec<String>,
+}
+
+impl ToolchainFile {
+    fn load(root: &Path) -> Result<Option<Self>> {
+        let (path, contents) = match ["rust-toolchain.toml", "rust-toolchain"]
+            .iter()
+            .map(|name| root.join(name))
+            .find(|path| path.exists())
+        {
+            Some(path) => {
+                let contents = fs::read_to_string(&path).map_err(FreightError::io(&path))?;
+                (path, contents)
+            }
+            None => return Ok(None),
+        };
+        if path.extension().is_none() && !contents.contains('[') {
+            return Ok(Some(Self {
+                channel: Some(contents.trim().to_string()),
+                ..Self::default()
+            }));
+        }
+
+        let (table, spans) = toml::parse(&contents)
+            .map_err(|e| FreightError::manifest(&path, &contents, Some(e.span), e.message, None))?;
+        let invalid = |key: &str, message: String| {
+            FreightError::manifest(&path, &contents, spans.value(key), message, None)
+        };
+        let mut file = Self::default();
+        let Some(Value::Table(toolchain)) = table.get("toolchain") else {
+            return Err(invalid(
+                "toolchain",
+                "`toolchain` must be a table".to_string(),
+            ));
+        };
+        for (key, value) in toolchain {
+            match (key.as_str(), value) {
+                ("channel", Value::String(channel)) => file.channel = Some(channel.clone()),
+                ("path", Value::String(path)) => file.path = Some(path.into()),
+                ("components", Value::Array(components)) => {
+                    for component in components {
+                        let Value::String(component) = component else {
+                            return Err(invalid(
+                                "toolchain.components",
+                                "`toolchain.components` must only contain strings".into(),
+                            ));
+                        };
+                        file.components.push(component.clone());
+                    }
+                }
+                // Only rustup installs targets and picks profiles
+                ("targets", _) | ("profile", _) => {}
+                (key, value) => {
+                    return Err(invalid(
+                        &format!("toolchain.{key}"),
+                        format!(
+                            "`toolchain.{key}` is unsupported or can't be a {}",
+                            value.type_str()
+                        ),
+                    ))
+                }
+            }
+        }
+        Ok(Some(file))
+    }
+}
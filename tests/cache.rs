@@ -0,0 +1,119 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn cache_restores_artifacts() -> Result<()> {
+    let project = Project::new("cache_restores_artifacts")?
+        .file("Freight.toml", "name = \"cached\"\nedition = \"2021\"\n")?
+        .file(
+            ".freight/config.toml",
+            "[cache]\nenabled = true\ndir = \"cache\"\n",
+        )?
+        .file(
+            "src/lib.rs",
+            "pub fn answer() -> u32 {\n    let unused = 1;\n    42\n}\n",
+        )?
+        .file(
+            "src/main.rs",
+            "fn main() {\n    println!(\"{}\", cached::answer());\n}\n",
+        )?;
+    let stats = |project: &Project| -> Result<String> {
+        let output = project.freight()?.arg("cache").arg("stats").output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    assert!(project.freight()?.arg("build").output()?.status.success());
+    let stats_after_miss = stats(&project)?;
+    assert!(stats_after_miss.contains("     Entries 2 "));
+    assert!(stats_after_miss.contains("        Hits 0\n"));
+    assert!(stats_after_miss.contains("      Misses 2\n"));
+
+    fs::remove_dir_all(project.root().join("target"))?;
+    let output = project.freight()?.arg("build").output()?;
+    assert!(output.status.success());
+    // The warning rustc printed the first time is shown again
+    assert!(String::from_utf8(output.stderr)?.contains("unused variable: `unused`"));
+    let stats_after_hit = stats(&project)?;
+    assert!(stats_after_hit.contains("        Hits 2\n"));
+    assert!(stats_after_hit.contains("      Misses 2\n"));
+    assert!(stats_after_hit.contains("    Hit rate 50.0%\n"));
+    let bin = Command::new(project.root().join("target/debug/cached")).output()?;
+    assert_eq!(String::from_utf8(bin.stdout)?, "42\n");
+    Ok(())
+}
+
+#[test]
+fn cache_evicts_over_max_size() -> Result<()> {
+    let project = Project::new("cache_evicts_over_max_size")?
+        .file("Freight.toml", "name = \"evicted\"\nedition = \"2021\"\n")?
+        .file(
+            ".freight/config.toml",
+            "[cache]\nenabled = true\ndir = \"cache\"\nmax-size = \"1K\"\n",
+        )?
+        .file("src/lib.rs", "pub fn evicted() {}\n")?;
+    assert!(project.freight()?.arg("build").output()?.status.success());
+    let output = project.freight()?.arg("cache").arg("stats").output()?;
+    let stats = String::from_utf8(output.stdout)?;
+    assert!(stats.contains("     Entries 0 (0 B of 1.0 KiB)\n"));
+    assert!(stats.contains("      Misses 1\n"));
+
+    let project = project.file(
+        ".freight/config.toml",
+        "[cache]\nenabled = true\nmax-size = \"lots\"\n",
+    )?;
+    let output = project.freight()?.arg("build").output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("`lots` is not a valid size"));
+    Ok(())
+}
+
+#[test]
+fn checkouts_share_the_cache() -> Result<()> {
+    let mut project = Project::new("checkouts_share_the_cache")?;
+    let config = format!(
+        "[cache]\nenabled = true\ndir = \"{}\"\n",
+        project.root().join("cache").display()
+    );
+    for checkout in ["one", "two"] {
+        project = project
+            .file(
+                format!("{checkout}/Freight.toml"),
+                "name = \"shared\"\nedition = \"2021\"\n",
+            )?
+            .file(format!("{checkout}/.freight/config.toml"), &config)?
+            .file(
+                format!("{checkout}/src/main.rs"),
+                "fn main() {\n    let unused = 1;\n}\n",
+            )?;
+    }
+    let build = |checkout: &str| -> Result<std::process::Output> {
+        Ok(project
+            .freight()?
+            .current_dir(project.root().join(checkout))
+            .arg("build")
+            .output()?)
+    };
+    assert!(build("one")?.status.success());
+    let output = build("two")?;
+    assert!(output.status.success());
+    // The warning points at the checkout that was built
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("two/src/main.rs:2:9"));
+    assert!(!stderr.contains("one/src/main.rs"));
+
+    let output = project
+        .freight()?
+        .current_dir(project.root().join("two"))
+        .args(["cache", "stats"])
+        .output()?;
+    let stats = String::from_utf8(output.stdout)?;
+    assert!(stats.contains("     Entries 1 "));
+    assert!(stats.contains("        Hits 1\n"));
+    assert!(stats.contains("      Misses 1\n"));
+    Ok(())
+}
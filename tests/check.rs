@@ -0,0 +1,82 @@
+mod common;
+
+use common::Project;
+use common::Result;
+
+const UTIL_TOML: &str = "name = \"util\"\nedition = \"2021\"\n";
+const APP_TOML: &str =
+    "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\nutil = { path = \"../util\" }\n";
+
+#[test]
+fn check_only_emits_metadata() -> Result<()> {
+    let project = Project::new("check_only_emits_metadata")?
+        .file("util/Freight.toml", UTIL_TOML)?
+        .file(
+            "util/src/lib.rs",
+            "pub fn double(x: u32) -> u32 {\n    x * 2\n}\n",
+        )?
+        .file("app/Freight.toml", APP_TOML)?
+        .file(
+            "app/src/main.rs",
+            "fn main() {\n    println!(\"{}\", util::double(2));\n}\n",
+        )?;
+    let app = project.root().join("app");
+    let output = project.freight()?.current_dir(&app).arg("check").output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Checking lib util"));
+    assert!(stderr.contains("Checking bin app"));
+
+    let check = app.join("target/debug/check");
+    assert!(check.join("libutil.rmeta").exists());
+    assert!(!check.join("libutil.rlib").exists());
+    assert!(!check.join("app").exists());
+    assert!(!app.join("target/debug/app").exists());
+
+    // Nothing changed so everything is fresh
+    let output = project.freight()?.current_dir(&app).arg("check").output()?;
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stderr)?.contains("Checking"));
+    Ok(())
+}
+
+#[test]
+fn check_reports_type_errors() -> Result<()> {
+    let project = Project::new("check_reports_type_errors")?
+        .file("util/Freight.toml", UTIL_TOML)?
+        .file(
+            "util/src/lib.rs",
+            "pub fn double(x: u32) -> u32 {\n    x * 2\n}\n",
+        )?
+        .file("app/Freight.toml", APP_TOML)?
+        .file(
+            "app/src/main.rs",
+            "fn main() {\n    let _: String = util::double(2);\n}\n",
+        )?;
+    let output = project
+        .freight()?
+        .current_dir(project.root().join("app"))
+        .arg("check")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("mismatched types"));
+    Ok(())
+}
+
+#[test]
+fn check_tests() -> Result<()> {
+    let project = Project::new("check_tests")?
+        .file("Freight.toml", "name = \"checked\"\nedition = \"2021\"\n")?
+        .file("src/lib.rs", "pub fn one() -> u32 {\n    1\n}\n")?
+        .file(
+            "tests/one.rs",
+            "#[test]\nfn one() {\n    let _: bool = checked::one();\n}\n",
+        )?;
+    let output = project.freight()?.arg("check").output()?;
+    assert!(output.status.success());
+
+    let output = project.freight()?.arg("check").arg("--tests").output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("mismatched types"));
+    Ok(())
+}
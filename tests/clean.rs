@@ -0,0 +1,96 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::fs;
+
+fn project(name: &str) -> Result<Project> {
+    Project::new(name)?
+        .file("dep/Freight.toml", "name = \"dep\"\nedition = \"2021\"\n")?
+        .file("dep/src/lib.rs", "pub fn dep() {}\n")?
+        .file(
+            "app/Freight.toml",
+            "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\ndep = { path = \"../dep\" }\n",
+        )?
+        .file("app/src/main.rs", "fn main() {\n    dep::dep();\n}\n")
+}
+
+#[test]
+fn clean_packages_and_dry_run() -> Result<()> {
+    let project = project("clean_packages_and_dry_run")?;
+    let app = project.root().join("app");
+    let debug = app.join("target/debug");
+    let freight = || -> Result<_> {
+        let mut freight = project.freight()?;
+        freight.current_dir(&app);
+        Ok(freight)
+    };
+    assert!(freight()?.arg("build").output()?.status.success());
+
+    let output = freight()?
+        .args(["clean", "-p", "dep", "--dry-run"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("    Removing target/debug/libdep.rlib\n"));
+    assert!(stderr.contains("    Removing target/debug/.fingerprint/lib-dep\n"));
+    assert!(!stderr.contains("bin-app"));
+    assert!(stderr.contains("     Summary "));
+    assert!(debug.join("libdep.rlib").exists());
+
+    let output = freight()?.args(["clean", "-p", "dep"]).output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("     Removed "));
+    assert!(!debug.join("libdep.rlib").exists());
+    assert!(!debug.join(".fingerprint/lib-dep").exists());
+    assert!(debug.join(".fingerprint/bin-app").exists());
+
+    let output = freight()?.args(["clean", "-p", "deps"]).output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("error: package `deps` is not in the dependency graph, did you mean `dep`?"));
+
+    // Only the release output would go, and there isn't any
+    let output = freight()?.args(["clean", "--release"]).output()?;
+    assert!(String::from_utf8(output.stderr)?.contains("     Removed 0 files, 0 B total\n"));
+    assert!(debug.exists());
+
+    assert!(freight()?.arg("clean").output()?.status.success());
+    assert!(!app.join("target").exists());
+    Ok(())
+}
+
+#[test]
+fn clean_gc_prunes_the_cache() -> Result<()> {
+    let project = project("clean_gc_prunes_the_cache")?.file(
+        "app/.freight/config.toml",
+        "[cache]\nenabled = true\ndir = \"cache\"\n",
+    )?;
+    let app = project.root().join("app");
+    let freight = || -> Result<_> {
+        let mut freight = project.freight()?;
+        freight.current_dir(&app);
+        Ok(freight)
+    };
+    assert!(freight()?.arg("build").output()?.status.success());
+    let entries = || -> Result<usize> { Ok(fs::read_dir(app.join("cache/entries"))?.count()) };
+    assert_eq!(entries()?, 2);
+
+    // Everything is still in the target directory
+    let output = freight()?.args(["clean", "--gc"]).output()?;
+    assert!(String::from_utf8(output.stderr)?.contains("     Removed 0 files, 0 B total\n"));
+    assert_eq!(entries()?, 2);
+
+    assert!(freight()?
+        .args(["clean", "-p", "app"])
+        .output()?
+        .status
+        .success());
+    assert!(freight()?
+        .args(["clean", "--gc"])
+        .output()?
+        .status
+        .success());
+    assert_eq!(entries()?, 1);
+    Ok(())
+}
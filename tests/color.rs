@@ -0,0 +1,61 @@
+mod common;
+
+use common::Project;
+use common::Result;
+
+fn project(name: &str) -> Result<Project> {
+    Project::new(name)?
+        .file("Freight.toml", "name = \"colorful\"\nedition = \"2021\"\n")?
+        .file(
+            "src/lib.rs",
+            "pub fn colorful() {\n    let unused = 1;\n}\n",
+        )
+}
+
+#[test]
+fn color_choice() -> Result<()> {
+    let project = project("color_choice")?;
+    let build = |args: &[&str], env: &[(&str, &str)]| -> Result<String> {
+        let mut freight = project.freight()?;
+        freight
+            .arg("build")
+            .args(args)
+            .env_remove("FREIGHT_TERM_COLOR")
+            .env_remove("NO_COLOR");
+        for (name, value) in env {
+            freight.env(name, value);
+        }
+        let output = freight.output()?;
+        assert!(output.status.success());
+        // Status lines go to stderr so stdout is only for program output
+        assert_eq!(String::from_utf8(output.stdout)?, "");
+        Ok(String::from_utf8(output.stderr)?)
+    };
+
+    let stderr = build(&["--color", "always"], &[])?;
+    assert!(stderr.contains("\u{1b}[1;32m   Compiling\u{1b}[0m lib colorful\n"));
+    assert!(stderr.contains("\u{1b}[1;33mwarning\u{1b}[0m: `colorful` (lib) generated 1 warning\n"));
+    // rustc's diagnostics keep their colors too
+    assert!(stderr.contains("\u{1b}[1m\u{1b}[33mwarning"));
+
+    let stderr = build(&[], &[("FREIGHT_TERM_COLOR", "always")])?;
+    assert!(stderr.contains("\u{1b}[1;32m    Artifact\u{1b}[0m"));
+
+    // The flag wins over the environment, and NO_COLOR only applies when
+    // nothing else says otherwise
+    let stderr = build(&["--color=never"], &[("FREIGHT_TERM_COLOR", "always")])?;
+    assert!(!stderr.contains('\u{1b}'));
+    let stderr = build(&[], &[("NO_COLOR", "1")])?;
+    assert!(!stderr.contains('\u{1b}'));
+    let stderr = build(&[], &[("NO_COLOR", "1"), ("FREIGHT_TERM_COLOR", "always")])?;
+    assert!(stderr.contains('\u{1b}'));
+
+    let output = project
+        .freight()?
+        .args(["build", "--color", "sometimes"])
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Unsupported color choice sometimes, expected one of auto, always or never"));
+    Ok(())
+}
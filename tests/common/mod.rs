@@ -0,0 +1,96 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use freight::root_dir;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Tests fail on any error, not just the ones Freight returns
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Targets that need neither a linker nor a libc. Any one of them being
+/// installed is enough to show that we can cross compile.
+const NO_STD_TARGETS: &[&str] = &[
+    "thumbv7em-none-eabihf",
+    "thumbv6m-none-eabi",
+    "wasm32-unknown-unknown",
+    "riscv32imac-unknown-none-elf",
+    "aarch64-unknown-none",
+    "x86_64-unknown-none",
+];
+
+/// The first target from [`NO_STD_TARGETS`] that has its standard library
+/// installed, if any of them do
+pub fn installed_no_std_target() -> Result<Option<&'static str>> {
+    let sysroot = Command::new("rustc")
+        .arg("--print")
+        .arg("sysroot")
+        .output()?
+        .stdout;
+    let rustlib = Path::new(String::from_utf8(sysroot)?.trim())
+        .join("lib")
+        .join("rustlib");
+    Ok(NO_STD_TARGETS
+        .iter()
+        .find(|target| rustlib.join(target).join("lib").exists())
+        .copied())
+}
+
+/// The host triple rustc compiles for by default
+pub fn host() -> Result<String> {
+    let output = Command::new("rustc").arg("-vV").output()?;
+    let host = String::from_utf8(output.stdout)?
+        .lines()
+        .find_map(|line| line.strip_prefix("host: ").map(String::from));
+    Ok(host.ok_or("rustc -vV has no host")?)
+}
+
+/// A throwaway project under `target/test_projects` that is deleted once the
+/// test is done with it
+pub struct Project {
+    root: PathBuf,
+}
+
+impl Project {
+    pub fn new(name: &str) -> Result<Self> {
+        let root = root_dir()?
+            .canonicalize()?
+            .join("target")
+            .join("test_projects")
+            .join(name);
+        if root.exists() {
+            fs::remove_dir_all(&root)?;
+        }
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    /// Write `contents` to `path` relative to the project root
+    pub fn file(self, path: impl AsRef<Path>, contents: &str) -> Result<Self> {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+        Ok(self)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// A `freight` command that runs inside of the project
+    pub fn freight(&self) -> Result<Command> {
+        let freight = root_dir()?.join("target").join("debug").join("freight");
+        let mut command = Command::new(freight);
+        command.current_dir(&self.root);
+        Ok(command)
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
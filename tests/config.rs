@@ -0,0 +1,112 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::process::Command;
+
+fn project(name: &str) -> Result<Project> {
+    Project::new(name)?
+        .file(
+            "home/config.toml",
+            "[build]\npipelining = false\njobs = 1\n",
+        )?
+        .file(
+            ".freight/config.toml",
+            "[build]\njobs = 2\n\n[cache]\nmax-size = \"1G\"\n",
+        )?
+        .file("app/Freight.toml", "name = \"app\"\nedition = \"2021\"\n")?
+        .file("app/.freight/config.toml", "[build]\njobs = 3\n")?
+        .file("app/src/main.rs", "fn main() {}\n")
+}
+
+fn freight(project: &Project) -> Result<Command> {
+    let mut freight = project.freight()?;
+    freight
+        .current_dir(project.root().join("app"))
+        .env("FREIGHT_HOME", project.root().join("home"))
+        .env_remove("FREIGHT_BUILD_JOBS");
+    Ok(freight)
+}
+
+#[test]
+fn layers_and_origins() -> Result<()> {
+    let project = project("layers_and_origins")?;
+    let root = project.root().display().to_string();
+
+    let output = freight(&project)?
+        .args(["config", "get", "build.jobs", "--show-origin"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        format!("build.jobs = 3 # {root}/app/.freight/config.toml\n")
+    );
+
+    let output = freight(&project)?
+        .args(["config", "get", "--show-origin"])
+        .env("FREIGHT_BUILD_JOBS", "4")
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        format!(
+            "build.jobs = \"4\" # environment variable `FREIGHT_BUILD_JOBS`\n\
+             build.pipelining = false # {root}/home/config.toml\n\
+             cache.max-size = \"1G\" # {root}/.freight/config.toml\n"
+        )
+    );
+
+    let output = freight(&project)?
+        .args(["config", "get", "cache"])
+        .output()?;
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "cache.max-size = \"1G\"\n"
+    );
+
+    let output = freight(&project)?
+        .args(["config", "get", "build.target"])
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .starts_with("error: config value `build.target` is not set"));
+    Ok(())
+}
+
+#[test]
+fn invalid_layers_are_reported() -> Result<()> {
+    let project = project("invalid_layers_are_reported")?;
+    assert!(freight(&project)?.arg("build").output()?.status.success());
+
+    // Values from the environment are strings until a key needs another type
+    let output = freight(&project)?
+        .arg("build")
+        .env("FREIGHT_BUILD_JOBS", "4")
+        .env("FREIGHT_BUILD_PIPELINING", "true")
+        .env("FREIGHT_BUILD_TARGET_DIR", "2024")
+        .env("FREIGHT_CACHE_ENABLED", "false")
+        .output()?;
+    assert!(output.status.success());
+    assert!(project.root().join("app/2024/debug/app").exists());
+
+    let output = freight(&project)?
+        .arg("build")
+        .env("FREIGHT_BUILD_JOBS", "many")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.starts_with(
+        "error: FREIGHT_BUILD_JOBS: `build.jobs` must be a positive integer, found string"
+    ));
+
+    // Errors point at the file the bad value came from
+    let project = project.file(".freight/config.toml", "[cache]\nmax-size = \"lots\"\n")?;
+    let output = freight(&project)?.arg("build").output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("`lots` is not a valid size"));
+    assert!(stderr.contains(&format!(
+        " --> {}/.freight/config.toml:2:12",
+        project.root().display()
+    )));
+    Ok(())
+}
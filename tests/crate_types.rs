@@ -0,0 +1,90 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::env;
+
+const FFI_TOML: &str = "name = \"ffi\"
+edition = \"2021\"
+
+[lib]
+crate-type = [\"rlib\", \"cdylib\", \"staticlib\"]
+name = \"ffi_math\"
+path = \"src/ffi.rs\"
+";
+
+const FFI_LIB: &str = "#[no_mangle]
+pub extern \"C\" fn ffi_add(a: i32, b: i32) -> i32 {
+    a + b
+}
+";
+
+#[cfg(target_os = "linux")]
+mod dl {
+    use std::ffi::c_char;
+    use std::ffi::c_int;
+    use std::ffi::c_void;
+
+    pub const RTLD_NOW: c_int = 2;
+
+    #[link(name = "dl")]
+    extern "C" {
+        pub fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+        pub fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        pub fn dlclose(handle: *mut c_void) -> c_int;
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn cdylib_can_be_loaded() -> Result<()> {
+    use std::ffi::CString;
+    use std::mem;
+
+    let project = Project::new("cdylib_can_be_loaded")?
+        .file("Freight.toml", FFI_TOML)?
+        .file("src/ffi.rs", FFI_LIB)?;
+    let output = project.freight()?.arg("build").output()?;
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr)?;
+    let debug = project.root().join("target").join("debug");
+    for artifact in ["libffi_math.rlib", "libffi_math.so", "libffi_math.a"] {
+        assert!(debug.join(artifact).exists(), "{artifact} was not built");
+        assert!(stderr.contains(&format!("Artifact target/debug/{artifact}")));
+    }
+
+    let path = CString::new(debug.join("libffi_math.so").to_str().unwrap())?;
+    let symbol = CString::new("ffi_add")?;
+    unsafe {
+        let handle = dl::dlopen(path.as_ptr(), dl::RTLD_NOW);
+        assert!(!handle.is_null(), "failed to dlopen libffi_math.so");
+        let ffi_add = dl::dlsym(handle, symbol.as_ptr());
+        assert!(!ffi_add.is_null(), "ffi_add was not exported");
+        let ffi_add: extern "C" fn(i32, i32) -> i32 = mem::transmute(ffi_add);
+        assert_eq!(ffi_add(2, 3), 5);
+        dl::dlclose(handle);
+    }
+    Ok(())
+}
+
+#[test]
+fn renamed_lib_is_linked_by_name() -> Result<()> {
+    let project = Project::new("renamed_lib_is_linked_by_name")?
+        .file("Freight.toml", FFI_TOML)?
+        .file("src/ffi.rs", FFI_LIB)?
+        .file(
+            "src/main.rs",
+            "fn main() {\n    println!(\"sum: {}\", ffi_math::ffi_add(2, 3));\n}\n",
+        )?;
+    let output = project.freight()?.arg("run").output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("sum: 5"));
+    let cdylib = format!(
+        "{}ffi_math{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    );
+    assert!(project.root().join("target/debug").join(cdylib).exists());
+    Ok(())
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::Project;
+use common::Result;
+
+#[test]
+fn warnings_are_summarized_and_replayed() -> Result<()> {
+    let project = Project::new("warnings_are_summarized_and_replayed")?
+        .file("Freight.toml", "name = \"noisy\"\nedition = \"2021\"\n")?
+        .file(
+            "src/lib.rs",
+            "pub fn noisy() {\n    let a = 1;\n    let b = 2;\n}\n",
+        )?;
+    let output = project.freight()?.arg("build").output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("   Compiling lib noisy\n"));
+    assert!(stderr.contains("unused variable: `a`"));
+    assert!(stderr.contains("unused variable: `b`"));
+    assert!(stderr.contains("warning: `noisy` (lib) generated 2 warnings"));
+    // rustc's own summary is replaced by ours and there are no colors when
+    // stderr isn't a terminal
+    assert!(!stderr.contains("warnings emitted"));
+    assert!(!stderr.contains('\u{1b}'));
+
+    // The lib is fresh but its warnings are still shown
+    let output = project.freight()?.arg("build").output()?;
+    assert!(output.status.success());
+    let replayed = String::from_utf8(output.stderr)?;
+    assert!(!replayed.contains("Compiling"));
+    // Everything but how long the build took is the same
+    let until_finished = |stderr: &str| stderr.split("    Finished").next().unwrap().to_string();
+    assert_eq!(
+        until_finished(&replayed),
+        until_finished(&stderr).replace("   Compiling lib noisy\n", "")
+    );
+    Ok(())
+}
+
+#[test]
+fn errors_are_counted() -> Result<()> {
+    let project = Project::new("errors_are_counted")?
+        .file("Freight.toml", "name = \"broken\"\nedition = \"2021\"\n")?
+        .file(
+            "src/main.rs",
+            "fn main() {\n    let _: u8 = \"one\";\n    let _: u8 = \"two\";\n}\n",
+        )?;
+    let output = project.freight()?.arg("build").output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("mismatched types"));
+    assert!(stderr
+        .contains("error: could not compile `broken` (bin \"broken\") due to 2 previous errors\n"));
+    assert!(!stderr.contains("aborting due to"));
+    Ok(())
+}
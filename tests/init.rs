@@ -1,8 +1,12 @@
+mod common;
+
+use common::Result;
 use freight::config::Manifest;
 use freight::init;
 use freight::root_dir;
+use freight::rustc;
 use freight::rustc::Edition;
-use freight::Result;
+use freight::toolchain::Toolchain;
 use std::fs;
 
 #[test]
@@ -52,7 +56,7 @@ fn initialize_project() -> Result<()> {
             )
             .into());
         }
-        if freight.edition != Edition::E2021 {
+        if freight.edition != Edition::latest(&rustc::version(&Toolchain::default())?) {
             return Err(format!(
                 "Freight.toml edition was incorrect. Value was '{:?}'",
                 freight.edition
@@ -0,0 +1,132 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+#[test]
+fn links_must_be_unique() -> Result<()> {
+    let project = Project::new("links_must_be_unique")?
+        .file(
+            "a/Freight.toml",
+            "name = \"a\"\nedition = \"2021\"\nlinks = \"z\"\n",
+        )?
+        .file("a/src/lib.rs", "")?
+        .file(
+            "b/Freight.toml",
+            "name = \"b\"\nedition = \"2021\"\nlinks = \"z\"\n",
+        )?
+        .file("b/src/lib.rs", "")?
+        .file(
+            "app/Freight.toml",
+            "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\n\
+             a = { path = \"../a\" }\nb = { path = \"../b\" }\n",
+        )?
+        .file("app/src/main.rs", "fn main() {}\n")?;
+    let output = project
+        .freight()?
+        .current_dir(project.root().join("app"))
+        .arg("build")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains(
+        "Packages a and b both link to the native library z, a native library can only be linked once"
+    ));
+    Ok(())
+}
+
+#[test]
+fn links_overrides_and_linker() -> Result<()> {
+    let project = Project::new("links_overrides_and_linker")?
+        .file(
+            "native/answer.c",
+            "unsigned int answer(void) { return 42; }\n",
+        )?
+        .file(
+            "linker.sh",
+            "#!/bin/sh\ntouch \"$(dirname \"$0\")/linker-ran\"\nexec cc \"$@\"\n",
+        )?
+        .file(
+            "sys/Freight.toml",
+            "name = \"sys\"\nedition = \"2021\"\nlinks = \"answer\"\n",
+        )?
+        .file(
+            "sys/src/lib.rs",
+            "#[cfg(not(has_answer))]\ncompile_error!(\"rustc-cfg wasn't passed\");\n\n\
+             extern \"C\" {\n    fn answer() -> u32;\n}\n\n\
+             pub fn safe_answer() -> u32 {\n    unsafe { answer() }\n}\n",
+        )?
+        .file(
+            "app/Freight.toml",
+            "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\nsys = { path = \"../sys\" }\n",
+        )?
+        .file(
+            "app/src/main.rs",
+            "fn main() {\n    println!(\"{}\", sys::safe_answer());\n}\n",
+        )?;
+    let root = project.root();
+    let linker = root.join("linker.sh");
+    fs::set_permissions(&linker, fs::Permissions::from_mode(0o755))?;
+    let object = root.join("native/answer.o");
+    let compiled = Command::new("cc")
+        .arg("-c")
+        .arg(root.join("native/answer.c"))
+        .arg("-o")
+        .arg(&object)
+        .status()
+        .and_then(|_| {
+            Command::new("ar")
+                .arg("rcs")
+                .arg(root.join("native/libanswer.a"))
+                .arg(&object)
+                .status()
+        });
+    if !compiled.is_ok_and(|status| status.success()) {
+        eprintln!("skipping: no C compiler or archiver to build a native library with");
+        return Ok(());
+    }
+
+    // Search paths are relative to the folder holding `.freight`
+    let project = project.file(
+        ".freight/config.toml",
+        &format!(
+            "[target.{host}]\nlinker = \"./linker.sh\"\n\n\
+             [target.{host}.answer]\nrustc-link-lib = [\"static=answer\"]\n\
+             rustc-link-search = [\"native\"]\nrustc-cfg = \"has_answer\"\n",
+            host = common::host()?
+        ),
+    )?;
+    let app = project.root().join("app");
+    let output = project
+        .freight()?
+        .current_dir(&app)
+        .arg("build")
+        .env_remove("RUSTFLAGS")
+        .env_remove("FREIGHT_ENCODED_RUSTFLAGS")
+        .output()?;
+    assert!(output.status.success());
+    assert!(project.root().join("linker-ran").exists());
+    let output = Command::new(app.join("target/debug/app")).output()?;
+    assert_eq!(String::from_utf8(output.stdout)?, "42\n");
+    Ok(())
+}
+
+#[test]
+fn archivers_are_ignored() -> Result<()> {
+    let project = Project::new("archivers_are_ignored")?
+        .file("Freight.toml", "name = \"app\"\nedition = \"2021\"\n")?
+        .file("src/lib.rs", "")?
+        .file(
+            ".freight/config.toml",
+            "[target.x86_64-unknown-linux-gnu]\nar = \"llvm-ar\"\n",
+        )?;
+    let output = project.freight()?.arg("build").output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.starts_with(
+        "warning: `target.x86_64-unknown-linux-gnu.ar` is ignored, \
+         rustc never runs an archiver\n"
+    ));
+    Ok(())
+}
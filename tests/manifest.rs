@@ -1,4 +1,21 @@
+mod common;
+
+use common::Project;
+use common::Result;
 use freight::config::Manifest;
+use freight::FreightError;
+use std::path::Path;
+
+/// The error `freight build` prints for a project with this manifest
+fn build_error(name: &str, manifest: &str) -> Result<String> {
+    let project = Project::new(name)?
+        .file("Freight.toml", manifest)?
+        .file("src/lib.rs", "")?;
+    let output = project.freight()?.arg("build").output()?;
+    assert!(!output.status.success());
+    let path = project.root().join("Freight.toml");
+    Ok(String::from_utf8(output.stderr)?.replace(&path.display().to_string(), "Freight.toml"))
+}
 
 #[test]
 fn can_parse_good_manifest() {
@@ -7,10 +24,82 @@ fn can_parse_good_manifest() {
 
 #[test]
 fn will_fail_bad_manifest() {
+    let err = Manifest::parse_from_file("tests/Freight_Bad_Fixture.toml").unwrap_err();
+    assert!(matches!(
+        err,
+        FreightError::Manifest(ref error)
+            if error.path == Path::new("tests/Freight_Bad_Fixture.toml")
+                && error.message == "Field bad_field is unsupported"
+    ));
+}
+
+#[test]
+fn missing_manifest_keeps_the_io_error() {
+    let err = Manifest::parse_from_file("tests/Freight_Missing.toml").unwrap_err();
+    assert!(matches!(
+        err,
+        FreightError::Io { path: Some(ref path), ref source }
+            if path == Path::new("tests/Freight_Missing.toml")
+                && source.kind() == std::io::ErrorKind::NotFound
+    ));
+}
+
+#[test]
+fn unknown_fields_point_at_the_key() -> Result<()> {
+    let stderr = build_error(
+        "unknown_fields_point_at_the_key",
+        "name = \"typo\"\neditoin = \"2021\"\n",
+    )?;
     assert_eq!(
-        Manifest::parse_from_file("tests/Freight_Bad_Fixture.toml")
-            .unwrap_err()
-            .to_string(),
-        "Field bad_field is unsupported".to_string()
+        stderr,
+        "error: Field editoin is unsupported\n \
+         --> Freight.toml:2:1\n  \
+         |\n\
+         2 | editoin = \"2021\"\n  \
+         | ^^^^^^^\n  \
+         = help: did you mean `edition`?\n"
     );
+    Ok(())
+}
+
+#[test]
+fn invalid_values_point_at_the_value() -> Result<()> {
+    let stderr = build_error(
+        "invalid_values_point_at_the_value",
+        "name = \"editions\"\nedition = \"2022\"\n",
+    )?;
+    assert!(stderr.contains("error: Edition 2022 is not supported\n"));
+    assert!(stderr.contains("2 | edition = \"2022\"\n  |           ^^^^^^\n"));
+    assert!(stderr.contains("= help: supported editions are `2015`, `2018`, `2021`, `2024`"));
+
+    let stderr = build_error(
+        "invalid_values_point_at_the_value",
+        "name = \"reqs\"\nedition = \"2021\"\n\
+         [dependencies]\nfoo = { path = \"../foo\", version = \"one\" }\n",
+    )?;
+    assert!(stderr.contains("`one` is not a valid version requirement"));
+    assert!(stderr.contains(" --> Freight.toml:4:36\n"));
+    assert!(stderr.contains("  |                                    ^^^^^\n"));
+    Ok(())
+}
+
+#[test]
+fn conflicting_dependencies() -> Result<()> {
+    let stderr = build_error(
+        "conflicting_dependencies",
+        "name = \"conflicts\"\nedition = \"2021\"\n\
+         [dependencies]\n\
+         bar = { path = \"../foo\" }\n\
+         foo = { path = \"../foo\" }\n",
+    )?;
+    assert!(stderr.contains("Dependencies bar and foo conflict, they both point to ../foo"));
+    assert!(stderr.contains("5 | foo = { path = \"../foo\" }\n  |                ^^^^^^^^\n"));
+
+    let stderr = build_error(
+        "conflicting_dependencies",
+        "name = \"registry\"\nedition = \"2021\"\n[dependencies]\nfoo = \"1.0\"\n",
+    )?;
+    assert!(stderr.contains("Dependency foo has no path, registry dependencies are unsupported"));
+    assert!(stderr.contains("= help: use a path dependency like `foo = { path = \"../foo\" }`"));
+    Ok(())
 }
@@ -0,0 +1,126 @@
+mod common;
+
+use common::Project;
+use common::Result;
+
+const TOML: &str = "name = \"messages\"\nedition = \"2021\"\n";
+const LIB: &str = "pub fn unused() {\n    let x = 1;\n}\n";
+const MAIN: &str = "fn main() {\n    messages::unused();\n}\n";
+
+#[test]
+fn json_messages() -> Result<()> {
+    let project = Project::new("json_messages")?
+        .file("Freight.toml", TOML)?
+        .file("src/lib.rs", LIB)?
+        .file("src/main.rs", MAIN)?;
+    let output = project
+        .freight()?
+        .arg("build")
+        .arg("--message-format=json")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines = stdout.lines().collect::<Vec<_>>();
+
+    // Nothing but JSON on stdout, with the status lines on stderr instead
+    assert!(lines.iter().all(|line| line.starts_with('{')));
+    assert!(String::from_utf8(output.stderr)?.contains("Compiling lib messages"));
+
+    let message = lines
+        .iter()
+        .find(|line| line.contains(r#""reason":"compiler-message""#))
+        .expect("a compiler message");
+    assert!(message.contains(r#""$message_type":"diagnostic""#));
+    assert!(message.contains("unused variable: `x`"));
+    assert!(message.contains(r#""kind":["lib"]"#));
+    // Rendered as plain text like Cargo does, without rustc's colors
+    assert!(message.contains(r#""rendered":"warning: unused variable"#));
+    assert!(!stdout.contains(r"\u001b"));
+
+    let lib = lines
+        .iter()
+        .find(|line| {
+            line.contains(r#""reason":"compiler-artifact""#) && line.contains("libmessages.rlib")
+        })
+        .expect("an artifact for the library");
+    assert!(lib.contains(r#""executable":null"#));
+    assert!(lib.contains(r#""fresh":false"#));
+    let bin = lines
+        .iter()
+        .find(|line| {
+            line.contains(r#""reason":"compiler-artifact""#) && line.contains(r#""kind":["bin"]"#)
+        })
+        .expect("an artifact for the binary");
+    assert!(bin.contains(&format!(
+        r#""executable":"{}""#,
+        project.root().join("target/debug/messages").display()
+    )));
+    assert_eq!(
+        lines.last(),
+        Some(&r#"{"reason":"build-finished","success":true}"#)
+    );
+
+    // Fresh units still report their artifacts and replay their messages
+    let output = project
+        .freight()?
+        .arg("build")
+        .arg("--message-format")
+        .arg("json")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(r#""fresh":true"#));
+    assert!(stdout.contains("unused variable: `x`"));
+    Ok(())
+}
+
+#[test]
+fn short_messages() -> Result<()> {
+    let project = Project::new("short_messages")?
+        .file("Freight.toml", TOML)?
+        .file("src/lib.rs", LIB)?;
+    let output = project
+        .freight()?
+        .arg("check")
+        .arg("--message-format=short")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("src/lib.rs:2:9: warning: unused variable: `x`\n"));
+    assert!(!stderr.contains("-->"));
+
+    let output = project
+        .freight()?
+        .arg("check")
+        .arg("--message-format=json-diagnostic-short")
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?
+        .contains(r#"/src/lib.rs:2:9: warning: unused variable: `x`\n""#));
+    Ok(())
+}
+
+#[test]
+fn failed_build_is_reported() -> Result<()> {
+    let project = Project::new("failed_build_is_reported")?
+        .file("Freight.toml", TOML)?
+        .file("src/main.rs", "fn main() {\n    let _: u8 = \"one\";\n}\n")?;
+    let output = project
+        .freight()?
+        .arg("build")
+        .arg("--message-format=json")
+        .output()?;
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(r#""level":"error""#));
+    assert!(stdout.ends_with("{\"reason\":\"build-finished\",\"success\":false}\n"));
+
+    let output = project
+        .freight()?
+        .arg("build")
+        .arg("--message-format=yaml")
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("Unsupported message format yaml"));
+    Ok(())
+}
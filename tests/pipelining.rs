@@ -0,0 +1,181 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::fs;
+use std::thread;
+
+/// How many crates are in the chain, each depending on the one before it
+const CHAIN_LENGTH: usize = 4;
+
+/// A chain of libraries with plenty of codegen each, ending in a binary that
+/// uses all of them
+fn chain(name: &str, pipelining: bool) -> Result<Project> {
+    let mut project = Project::new(name)?.file(
+        "app/.freight/config.toml",
+        // Two jobs so dependents can start while codegen is still running
+        &format!("[build]\npipelining = {pipelining}\njobs = 2\n"),
+    )?;
+    for index in 0..CHAIN_LENGTH {
+        let mut toml = format!("name = \"chain{index}\"\nedition = \"2021\"\n");
+        let mut lib = String::new();
+        if index > 0 {
+            let dep = index - 1;
+            toml.push_str(&format!(
+                "\n[dependencies]\nchain{dep} = {{ path = \"../chain{dep}\" }}\n"
+            ));
+            lib.push_str(&format!("pub use chain{dep}::*;\n"));
+        }
+        for function in 0..300 {
+            lib.push_str(&format!(
+                "pub fn f{index}_{function}(x: u64) -> u64 {{\n    \
+                 let mut v = vec![x; {function} + 1];\n    \
+                 for (i, n) in v.iter_mut().enumerate() {{\n        \
+                 *n = n.wrapping_mul(i as u64 + {index}).rotate_left(3) ^ 0x5555;\n    \
+                 }}\n    \
+                 v.iter().copied().fold(0, u64::wrapping_add)\n}}\n"
+            ));
+        }
+        project = project
+            .file(format!("chain{index}/Freight.toml"), &toml)?
+            .file(format!("chain{index}/src/lib.rs"), &lib)?;
+    }
+    let last = CHAIN_LENGTH - 1;
+    project
+        .file(
+            "app/Freight.toml",
+            &format!(
+                "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\nchain{last} = {{ path = \"../chain{last}\" }}\n"
+            ),
+        )?
+        .file(
+            "app/src/main.rs",
+            &format!("fn main() {{\n    println!(\"{{}}\", chain{last}::f0_1(2) + chain{last}::f{last}_2(3));\n}}\n"),
+        )
+}
+
+/// What the `--timings` report says about building the chain
+struct Report {
+    /// How long the whole build took, in seconds
+    duration: f64,
+    /// When each library in the chain started, had its metadata ready and
+    /// finished, in that order
+    units: Vec<[f64; 3]>,
+}
+
+fn build(project: &Project) -> Result<Report> {
+    let app = project.root().join("app");
+    let output = project
+        .freight()?
+        .current_dir(&app)
+        .args(["build", "--timings"])
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8(output.stderr)?
+    );
+    let output = project.freight()?.current_dir(&app).arg("run").output()?;
+    assert!(output.status.success());
+
+    let reports = app.join("target/freight-timings");
+    let report = fs::read_dir(&reports)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .find(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .expect("a JSON timing report");
+    let report = fs::read_to_string(report)?;
+    let units = entries(field(&report, "units"));
+    let time = |unit: &str, key: &str| field(unit, key).parse().unwrap_or(f64::NAN);
+    Ok(Report {
+        duration: time(&report, "duration"),
+        units: (0..CHAIN_LENGTH)
+            .map(|index| {
+                let name = format!("\"`chain{index}` (lib)\"");
+                let unit = units
+                    .iter()
+                    .find(|unit| field(unit, "name") == name)
+                    .expect("a unit for the library");
+                [time(unit, "start"), time(unit, "rmeta"), time(unit, "end")]
+            })
+            .collect(),
+    })
+}
+
+/// The entries of a compact JSON object or array, split on the commas that
+/// aren't inside a string or a nested object or array
+fn entries(json: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut depth = 0;
+    let mut start = 1;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in json.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 0 && start < index {
+                    entries.push(&json[start..index]);
+                }
+            }
+            ',' if depth == 1 => {
+                entries.push(&json[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// The value of `key` in a compact JSON object, as JSON
+fn field<'a>(object: &'a str, key: &str) -> &'a str {
+    let key = format!("\"{key}\":");
+    entries(object)
+        .into_iter()
+        .find_map(|entry| entry.strip_prefix(&key))
+        .unwrap_or_else(|| panic!("no {key} in {object}"))
+}
+
+#[test]
+fn pipelining_starts_dependents_early() -> Result<()> {
+    let pipelined = build(&chain("pipelining_pipelined", true)?)?;
+    for pair in pipelined.units.windows(2) {
+        let [[_, rmeta, end], [start, _, _]] = pair else {
+            unreachable!()
+        };
+        assert!(rmeta <= start && start < end, "{:?}", pipelined.units);
+    }
+
+    // Without pipelining there is no separate metadata to start on
+    let sequential = build(&chain("pipelining_sequential", false)?)?;
+    for pair in sequential.units.windows(2) {
+        let [[_, rmeta, end], [start, _, _]] = pair else {
+            unreachable!()
+        };
+        assert!(rmeta.is_nan() && end <= start, "{:?}", sequential.units);
+    }
+
+    // Overlapping codegen only pays off with more than one core to run on
+    if thread::available_parallelism()?.get() > 1 {
+        assert!(
+            pipelined.duration < sequential.duration,
+            "the chain took {:.2}s to build with pipelining and {:.2}s without",
+            pipelined.duration,
+            sequential.duration
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::env;
+
+const MACROS_TOML: &str = "name = \"macros\"\nedition = \"2021\"\n\n[lib]\nproc-macro = true\n";
+const MACROS_LIB: &str = "use proc_macro::TokenStream;
+
+/// ```
+/// assert_eq!(macros::answer!(), 42);
+/// ```
+#[proc_macro]
+pub fn answer(_: TokenStream) -> TokenStream {
+    \"42\".parse().unwrap()
+}
+
+#[test]
+fn unit_test() {}
+";
+
+#[test]
+fn proc_macro_dependency() -> Result<()> {
+    let project = Project::new("proc_macro_dependency")?
+        .file("macros/Freight.toml", MACROS_TOML)?
+        .file("macros/src/lib.rs", MACROS_LIB)?
+        .file(
+            "app/Freight.toml",
+            "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\nmacros = { path = \"../macros\" }\n",
+        )?
+        .file(
+            "app/src/main.rs",
+            "fn main() {\n    println!(\"answer: {}\", macros::answer!());\n}\n",
+        )?;
+    let output = project
+        .freight()?
+        .current_dir(project.root().join("app"))
+        .arg("run")
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("answer: 42"));
+
+    let proc_macro = format!(
+        "{}macros{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    );
+    assert!(project
+        .root()
+        .join("app/target/debug")
+        .join(proc_macro)
+        .exists());
+    Ok(())
+}
+
+#[test]
+fn proc_macro_is_built_for_the_host() -> Result<()> {
+    let Some(target) = common::installed_no_std_target()? else {
+        eprintln!("skipping: no no_std target is installed");
+        return Ok(());
+    };
+    let project = Project::new("proc_macro_is_built_for_the_host")?
+        .file("macros/Freight.toml", MACROS_TOML)?
+        .file("macros/src/lib.rs", MACROS_LIB)?
+        .file(
+            "app/Freight.toml",
+            "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\nmacros = { path = \"../macros\" }\n",
+        )?
+        .file(
+            "app/src/lib.rs",
+            "#![no_std]\npub fn answer() -> u32 {\n    macros::answer!()\n}\n",
+        )?;
+    let status = project
+        .freight()?
+        .current_dir(project.root().join("app"))
+        .arg("build")
+        .arg("--target")
+        .arg(target)
+        .status()?;
+    assert!(status.success());
+
+    let target_dir = project.root().join("app/target");
+    let proc_macro = format!(
+        "{}macros{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    );
+    assert!(target_dir.join("debug").join(proc_macro).exists());
+    assert!(target_dir
+        .join(target)
+        .join("debug")
+        .join("libapp.rlib")
+        .exists());
+    Ok(())
+}
+
+#[test]
+fn proc_macro_tests() -> Result<()> {
+    let project = Project::new("proc_macro_tests")?
+        .file("Freight.toml", MACROS_TOML)?
+        .file("src/lib.rs", MACROS_LIB)?
+        .file(
+            "tests/expand.rs",
+            "#[test]\nfn expands() {\n    assert_eq!(macros::answer!(), 42);\n}\n",
+        )?;
+    let output = project.freight()?.arg("test").output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("test unit_test ... ok"));
+    assert!(stdout.contains("test expands ... ok"));
+    assert!(stdout.contains("src/lib.rs - answer (line 3) ... ok"));
+    Ok(())
+}
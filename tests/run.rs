@@ -0,0 +1,72 @@
+mod common;
+
+use common::Project;
+use common::Result;
+
+#[test]
+fn exit_code_is_passed_on() -> Result<()> {
+    let project = Project::new("exit_code_is_passed_on")?
+        .file("Freight.toml", "name = \"failing\"\nedition = \"2021\"\n")?
+        .file(
+            "src/main.rs",
+            "fn main() {\n    std::process::exit(std::env::args().len() as i32 + 1);\n}\n",
+        )?;
+    let output = project.freight()?.args(["run", "one", "two"]).output()?;
+    assert_eq!(output.status.code(), Some(4));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn binary_replaces_freight() -> Result<()> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let project = Project::new("binary_replaces_freight")?
+        .file("Freight.toml", "name = \"pid\"\nedition = \"2021\"\n")?
+        .file(
+            "src/main.rs",
+            "fn main() {\n    println!(\"{}\", std::process::id());\n    std::process::abort();\n}\n",
+        )?;
+    let child = project
+        .freight()?
+        .arg("run")
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    let pid = child.id();
+    let output = child.wait_with_output()?;
+    // There's no process in between, so the signal that killed the binary is
+    // seen instead of an exit code
+    assert_eq!(String::from_utf8(output.stdout)?.trim(), pid.to_string());
+    assert_eq!(output.status.signal(), Some(6));
+    Ok(())
+}
+
+#[test]
+fn flags_after_the_first_argument_go_to_the_binary() -> Result<()> {
+    let project = Project::new("flags_after_the_first_argument_go_to_the_binary")?
+        .file("Freight.toml", "name = \"echo\"\nedition = \"2021\"\n")?
+        .file(
+            "src/main.rs",
+            "fn main() {\n    let args = std::env::args().skip(1).collect::<Vec<_>>();\n    println!(\"{}\", args.join(\" \"));\n}\n",
+        )?;
+    let output = project
+        .freight()?
+        .args(["run", "app", "-q", "-v"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "app -q -v\n");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("   Compiling bin echo\n"));
+    assert!(!stderr.contains("     Running `"));
+
+    // Before the first argument they're still Freight's
+    let output = project
+        .freight()?
+        .args(["run", "-q", "app", "-q"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "app -q\n");
+    assert_eq!(String::from_utf8(output.stderr)?, "");
+    Ok(())
+}
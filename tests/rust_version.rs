@@ -0,0 +1,49 @@
+mod common;
+
+use common::Project;
+use common::Result;
+
+#[test]
+fn rust_version_is_enforced() -> Result<()> {
+    let project = Project::new("rust_version_is_enforced")?
+        .file(
+            "Freight.toml",
+            "name = \"msrv\"\nedition = \"2021\"\nrust-version = \"99.0\"\n",
+        )?
+        .file("src/lib.rs", "pub fn msrv() {}\n")?;
+    for command in ["build", "check"] {
+        let output = project.freight()?.arg(command).output()?;
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(stderr.starts_with("error: package msrv requires rustc >= 99.0 (you have "));
+    }
+
+    let output = project
+        .freight()?
+        .arg("build")
+        .arg("--ignore-rust-version")
+        .output()?;
+    assert!(output.status.success());
+    Ok(())
+}
+
+#[test]
+fn invalid_rust_version() -> Result<()> {
+    let project = Project::new("invalid_rust_version")?
+        .file(
+            "Freight.toml",
+            "name = \"msrv\"\nedition = \"2021\"\nrust-version = \"1.70.0-beta\"\n",
+        )?
+        .file("src/lib.rs", "")?;
+    let output = project.freight()?.arg("build").output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("`1.70.0-beta` is not a valid rust-version, expected something like `1.70`"));
+
+    let project = project.file(
+        "Freight.toml",
+        "name = \"msrv\"\nedition = \"2021\"\nrust-version = \"1.70\"\n",
+    )?;
+    assert!(project.freight()?.arg("build").output()?.status.success());
+    Ok(())
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::Project;
+use common::Result;
+
+fn project(name: &str) -> Result<Project> {
+    Project::new(name)?
+        .file("dep/Freight.toml", "name = \"dep\"\nedition = \"2021\"\n")?
+        .file(
+            "dep/src/lib.rs",
+            "#[cfg(extra)]\ncompile_error!(\"the dependency got the extra arguments\");\n\n\
+             pub fn dep() {}\n",
+        )?
+        .file(
+            "app/Freight.toml",
+            "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\ndep = { path = \"../dep\" }\n",
+        )?
+        .file(
+            "app/src/lib.rs",
+            "#[cfg(extra)]\npub fn extra() {}\n\npub fn app() {\n    dep::dep();\n}\n",
+        )?
+        .file(
+            "app/src/main.rs",
+            "fn main() {\n    println!(\"{}\", cfg!(extra));\n}\n",
+        )
+}
+
+#[test]
+fn rustc_passes_args_to_one_crate() -> Result<()> {
+    let project = project("rustc_passes_args_to_one_crate")?;
+    let app = project.root().join("app");
+    let freight = || -> Result<_> {
+        let mut freight = project.freight()?;
+        freight
+            .current_dir(&app)
+            .env_remove("RUSTFLAGS")
+            .env_remove("FREIGHT_ENCODED_RUSTFLAGS");
+        Ok(freight)
+    };
+
+    // A package with a library and a binary needs to be told which one
+    let output = freight()?
+        .args(["rustc", "--", "--cfg", "extra"])
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("pick one with `--lib`"));
+
+    let output = freight()?
+        .args(["rustc", "--lib", "--", "--cfg", "extra", "--emit=link,asm"])
+        .output()?;
+    assert!(output.status.success());
+    let debug = app.join("target/debug");
+    assert!(debug.join("app.s").exists());
+    assert!(!debug.join("app").exists());
+
+    let output = freight()?
+        .args(["rustc", "--bin", "app", "--", "--cfg", "extra"])
+        .output()?;
+    assert!(output.status.success());
+    let output = std::process::Command::new(debug.join("app")).output()?;
+    assert_eq!(String::from_utf8(output.stdout)?, "true\n");
+
+    let output = freight()?.args(["rustc", "--bin", "ap"]).output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("no binary named `ap`, did you mean `app`?"));
+    Ok(())
+}
+
+#[test]
+fn rustc_print_and_crate_type() -> Result<()> {
+    let project = project("rustc_print_and_crate_type")?;
+    let app = project.root().join("app");
+
+    let output = project
+        .freight()?
+        .current_dir(&app)
+        .args(["rustc", "--print", "cfg"])
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("target_os="));
+    assert!(!app.join("target").exists());
+
+    let output = project
+        .freight()?
+        .current_dir(&app)
+        .args(["rustc", "--lib", "--crate-type", "staticlib"])
+        .output()?;
+    assert!(output.status.success());
+    let debug = app.join("target/debug");
+    assert!(debug.join("libapp.a").exists());
+    assert!(!debug.join("libapp.rlib").exists());
+
+    let output = project
+        .freight()?
+        .current_dir(&app)
+        .args(["rustc", "--bin", "app", "--crate-type", "staticlib"])
+        .output()?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn rustdoc_passes_args() -> Result<()> {
+    let project = project("rustdoc_passes_args")?;
+    let app = project.root().join("app");
+    let output = project
+        .freight()?
+        .current_dir(&app)
+        .args(["rustdoc", "--", "--cfg", "extra"])
+        .env_remove("RUSTDOCFLAGS")
+        .env_remove("FREIGHT_ENCODED_RUSTDOCFLAGS")
+        .output()?;
+    assert!(output.status.success());
+    assert!(app.join("target/doc/app/fn.extra.html").exists());
+    Ok(())
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::process::Command;
+
+const MAIN: &str = "fn main() {
+    println!(
+        \"{} {} {} {}\",
+        cfg!(from_env),
+        cfg!(from_build),
+        cfg!(from_target),
+        cfg!(from_profile)
+    );
+}
+";
+
+/// Build the project with the given flags in the environment and run it
+fn cfgs(project: &Project, env: &[(&str, &str)]) -> Result<String> {
+    let mut freight = project.freight()?;
+    freight
+        .arg("build")
+        .env_remove("RUSTFLAGS")
+        .env_remove("FREIGHT_ENCODED_RUSTFLAGS");
+    for (name, value) in env {
+        freight.env(name, value);
+    }
+    assert!(freight.output()?.status.success());
+    let output = Command::new(project.root().join("target/debug/flags")).output()?;
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+#[test]
+fn rustflags_precedence() -> Result<()> {
+    let project = Project::new("rustflags_precedence")?
+        .file("Freight.toml", "name = \"flags\"\nedition = \"2021\"\n")?
+        .file("src/main.rs", MAIN)?;
+    assert_eq!(cfgs(&project, &[])?, "false false false false");
+
+    let project = project.file(
+        ".freight/config.toml",
+        "[build]\nrustflags = [\"--cfg\", \"from_build\"]\n\n\
+         [profile.dev]\nrustflags = \"--cfg from_profile\"\n",
+    )?;
+    // Changing the flags rebuilds the binary
+    assert_eq!(cfgs(&project, &[])?, "false true false true");
+
+    let project = project.file(
+        ".freight/config.toml",
+        &format!(
+            "[build]\nrustflags = [\"--cfg\", \"from_build\"]\n\n\
+             [target.{}]\nrustflags = \"--cfg from_target\"\n",
+            common::host()?
+        ),
+    )?;
+    assert_eq!(cfgs(&project, &[])?, "false false true false");
+    assert_eq!(
+        cfgs(&project, &[("RUSTFLAGS", "--cfg from_env")])?,
+        "true false false false"
+    );
+    assert_eq!(
+        cfgs(
+            &project,
+            &[
+                ("RUSTFLAGS", "--cfg from_build"),
+                ("FREIGHT_ENCODED_RUSTFLAGS", "--cfg\u{1f}from_env")
+            ]
+        )?,
+        "true false false false"
+    );
+
+    let project = project.file("src/main.rs", "fn main() {\n    let unused = 1;\n}\n")?;
+    let output = project
+        .freight()?
+        .arg("build")
+        .env("RUSTFLAGS", "-D warnings")
+        .env_remove("FREIGHT_ENCODED_RUSTFLAGS")
+        .output()?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn rustdocflags() -> Result<()> {
+    let project = Project::new("rustdocflags")?
+        .file("Freight.toml", "name = \"docs\"\nedition = \"2021\"\n")?
+        .file("src/lib.rs", "#[cfg(for_docs)]\npub fn only_in_docs() {}\n")?;
+    let output = project
+        .freight()?
+        .arg("doc")
+        .env("RUSTDOCFLAGS", "--cfg for_docs")
+        .env_remove("FREIGHT_ENCODED_RUSTDOCFLAGS")
+        .output()?;
+    assert!(output.status.success());
+    assert!(project
+        .root()
+        .join("target/doc/docs/fn.only_in_docs.html")
+        .exists());
+    Ok(())
+}
+
+#[test]
+fn rustflags_skip_host_units_when_cross_compiling() -> Result<()> {
+    let Some(target) = common::installed_no_std_target()? else {
+        eprintln!("skipping: no no_std target is installed");
+        return Ok(());
+    };
+    let project = Project::new("rustflags_skip_host_units_when_cross_compiling")?
+        .file(
+            "macros/Freight.toml",
+            "name = \"macros\"\nedition = \"2021\"\n\n[lib]\nproc-macro = true\n",
+        )?
+        .file(
+            "macros/src/lib.rs",
+            "#[cfg(for_target)]\ncompile_error!(\"the proc macro got the target's flags\");\n\n\
+             #[proc_macro]\npub fn answer(_: proc_macro::TokenStream) -> proc_macro::TokenStream {\n    \
+             \"42\".parse().unwrap()\n}\n",
+        )?
+        .file(
+            "app/Freight.toml",
+            "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\nmacros = { path = \"../macros\" }\n",
+        )?
+        .file(
+            "app/src/lib.rs",
+            "#![no_std]\n#[cfg(not(for_target))]\ncompile_error!(\"the target missed its flags\");\n\n\
+             pub fn answer() -> u32 {\n    macros::answer!()\n}\n",
+        )?;
+    let output = project
+        .freight()?
+        .current_dir(project.root().join("app"))
+        .args(["build", "--target", target])
+        .env("RUSTFLAGS", "--cfg for_target")
+        .env_remove("FREIGHT_ENCODED_RUSTFLAGS")
+        .output()?;
+    assert!(output.status.success());
+    Ok(())
+}
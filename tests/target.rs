@@ -0,0 +1,54 @@
+mod common;
+
+use common::Project;
+use common::Result;
+
+#[test]
+fn cross_compile_no_std_lib() -> Result<()> {
+    let Some(target) = common::installed_no_std_target()? else {
+        eprintln!("skipping: no no_std target is installed");
+        return Ok(());
+    };
+
+    let project = Project::new("cross_compile_no_std_lib")?
+        .file(
+            "Freight.toml",
+            "name = \"no_std_lib\"\nedition = \"2021\"\n",
+        )?
+        .file(
+            "src/lib.rs",
+            "#![no_std]\npub fn add(a: u32, b: u32) -> u32 {\n    a + b\n}\n",
+        )?;
+    let status = project
+        .freight()?
+        .arg("build")
+        .arg("--target")
+        .arg(target)
+        .status()?;
+    assert!(status.success());
+
+    let rlib = project
+        .root()
+        .join("target")
+        .join(target)
+        .join("debug")
+        .join("libno_std_lib.rlib");
+    assert!(rlib.exists(), "{} was not built", rlib.display());
+    assert!(!project.root().join("target").join("debug").exists());
+    Ok(())
+}
+
+#[test]
+fn unknown_target_is_rejected() -> Result<()> {
+    let project = Project::new("unknown_target_is_rejected")?
+        .file("Freight.toml", "name = \"unknown\"\nedition = \"2021\"\n")?
+        .file(
+            ".freight/config.toml",
+            "[build]\ntarget = \"not-a-real-target\"\n",
+        )?
+        .file("src/lib.rs", "")?;
+    let output = project.freight()?.arg("build").output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("not-a-real-target"));
+    Ok(())
+}
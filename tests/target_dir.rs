@@ -0,0 +1,81 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::env;
+
+fn project(name: &str) -> Result<Project> {
+    Project::new(name)?
+        .file("Freight.toml", "name = \"placed\"\nedition = \"2021\"\n")?
+        .file("src/lib.rs", "pub fn placed() {}\n")?
+        .file("src/main.rs", "fn main() {\n    println!(\"placed\");\n}\n")
+}
+
+#[test]
+fn target_dir_sources() -> Result<()> {
+    let project = project("target_dir_sources")?;
+    let bin = format!("placed{}", env::consts::EXE_SUFFIX);
+
+    let output = project
+        .freight()?
+        .args(["build", "--target-dir", "out", "--artifact-dir", "dist"])
+        .output()?;
+    assert!(output.status.success());
+    assert!(project.root().join("out/debug").join(&bin).exists());
+    assert!(project.root().join("dist").join(&bin).exists());
+    assert!(project.root().join("dist/libplaced.rlib").exists());
+    assert!(!project.root().join("target").exists());
+
+    let output = project
+        .freight()?
+        .arg("run")
+        .env("CARGO_TARGET_DIR", "from-cargo-env")
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?.lines().last(),
+        Some("placed")
+    );
+    assert!(project
+        .root()
+        .join("from-cargo-env/debug")
+        .join(&bin)
+        .exists());
+
+    // Freight's variable wins over Cargo's, and both over the config
+    let project = project.file(
+        ".freight/config.toml",
+        "[build]\ntarget-dir = \"from-config\"\n",
+    )?;
+    let output = project
+        .freight()?
+        .arg("build")
+        .env("FREIGHT_TARGET_DIR", "from-freight-env")
+        .env("CARGO_TARGET_DIR", "from-cargo-env")
+        .output()?;
+    assert!(output.status.success());
+    assert!(project
+        .root()
+        .join("from-freight-env/debug")
+        .join(&bin)
+        .exists());
+
+    let output = project
+        .freight()?
+        .arg("check")
+        .env_remove("FREIGHT_TARGET_DIR")
+        .env_remove("CARGO_TARGET_DIR")
+        .output()?;
+    assert!(output.status.success());
+    assert!(project.root().join("from-config/debug/check").exists());
+
+    let output = project
+        .freight()?
+        .arg("clean")
+        .env_remove("FREIGHT_TARGET_DIR")
+        .env_remove("CARGO_TARGET_DIR")
+        .output()?;
+    assert!(output.status.success());
+    assert!(!project.root().join("from-config").exists());
+    Ok(())
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::Project;
+use common::Result;
+use std::fs;
+
+#[test]
+fn timings_report() -> Result<()> {
+    let project = Project::new("timings_report")?
+        .file("dep/Freight.toml", "name = \"dep\"\nedition = \"2021\"\n")?
+        .file("dep/src/lib.rs", "pub fn dep() {}\n")?
+        .file(
+            "app/Freight.toml",
+            "name = \"app\"\nedition = \"2021\"\n\n[dependencies]\ndep = { path = \"../dep\" }\n",
+        )?
+        .file("app/src/main.rs", "fn main() {\n    dep::dep();\n}\n")?;
+    let app = project.root().join("app");
+    let output = project
+        .freight()?
+        .current_dir(&app)
+        .args(["build", "--timings"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("      Timing report saved to target/freight-timings/freight-timing-"));
+
+    let reports = app.join("target/freight-timings");
+    let mut names = fs::read_dir(&reports)?
+        .map(|entry| Ok(entry?.file_name().into_string().unwrap()))
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    let [html, json] = names.as_slice() else {
+        panic!("expected an HTML and a JSON report, found {names:?}");
+    };
+    assert!(html.starts_with("freight-timing-") && html.ends_with(".html"));
+    assert_eq!(json.replace(".json", ".html"), *html);
+
+    let html = fs::read_to_string(reports.join(html))?;
+    assert!(html.contains("<svg"));
+    assert!(html.contains("`dep` (lib)"));
+    let json = fs::read_to_string(reports.join(json))?;
+    assert!(json.contains("\"name\":\"`app` (bin \\\"app\\\")\""));
+    for field in [
+        "\"queued\":",
+        "\"start\":",
+        "\"rmeta\":",
+        "\"end\":",
+        "\"concurrency\":",
+    ] {
+        assert!(json.contains(field), "{field} missing from {json}");
+    }
+
+    // Reports from builds in the same second don't overwrite each other
+    for _ in 0..2 {
+        let output = project
+            .freight()?
+            .current_dir(&app)
+            .args(["build", "--timings"])
+            .output()?;
+        assert!(output.status.success());
+    }
+    assert_eq!(fs::read_dir(&reports)?.count(), 6);
+
+    // There's no report without the flag
+    fs::remove_dir_all(&reports)?;
+    let output = project.freight()?.current_dir(&app).arg("build").output()?;
+    assert!(output.status.success());
+    assert!(!reports.exists());
+    Ok(())
+}
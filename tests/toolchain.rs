@@ -0,0 +1,248 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Project;
+use common::Result;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+const TOML: &str = "name = \"toolchain\"\nedition = \"2021\"\n";
+const LIB: &str = "/// Docs\npub fn toolchain() {}\n";
+
+/// The real rustc or rustdoc, bypassing any rustup proxy which would follow
+/// the toolchain Freight picked
+fn real(program: &str) -> Result<PathBuf> {
+    let sysroot = Command::new("rustc")
+        .arg("--print")
+        .arg("sysroot")
+        .output()?
+        .stdout;
+    Ok(Path::new(String::from_utf8(sysroot)?.trim())
+        .join("bin")
+        .join(program))
+}
+
+/// Write a script at `path` that notes it was run as `name`, with its
+/// arguments, in the project's `invocations` file and then runs `exec`
+fn fake(project: Project, path: &str, name: &str, exec: &str) -> Result<Project> {
+    let log = project.root().join("invocations");
+    let project = project.file(
+        path,
+        &format!(
+            "#!/bin/sh\necho \"{name} $*\" >> {}\nexec {exec} \"$@\"\n",
+            log.display()
+        ),
+    )?;
+    let script = project.root().join(path);
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+    Ok(project)
+}
+
+/// What the fake programs were run with, with the project root taken out of
+/// the paths
+fn invocations(project: &Project) -> Result<String> {
+    let log = fs::read_to_string(project.root().join("invocations")).unwrap_or_default();
+    Ok(log.replace(&format!("{}/", project.root().display()), ""))
+}
+
+fn freight(project: &Project) -> Result<Command> {
+    let mut command = project.freight()?;
+    for var in ["RUSTC", "RUSTDOC", "RUSTC_WRAPPER", "RUSTUP_TOOLCHAIN"] {
+        command.env_remove(var);
+    }
+    Ok(command)
+}
+
+#[test]
+fn rustc_and_rustdoc_from_the_environment() -> Result<()> {
+    let project = Project::new("rustc_and_rustdoc_from_the_environment")?
+        .file("Freight.toml", TOML)?
+        .file("src/lib.rs", LIB)?;
+    let rustc = real("rustc")?.display().to_string();
+    let rustdoc = real("rustdoc")?.display().to_string();
+    let project = fake(project, "bin/rustc", "env-rustc", &rustc)?;
+    let project = fake(project, "bin/rustdoc", "env-rustdoc", &rustdoc)?;
+    let project = fake(project, "bin/wrapper", "wrapper", "")?;
+
+    let output = freight(&project)?
+        .arg("build")
+        .env("RUSTC", project.root().join("bin/rustc"))
+        .env("RUSTC_WRAPPER", project.root().join("bin/wrapper"))
+        .output()?;
+    assert!(output.status.success());
+    let log = invocations(&project)?;
+    // The wrapper gets the rustc to run, but only for compiling
+    assert!(log.contains("env-rustc -vV"));
+    assert!(log.contains("wrapper bin/rustc src/lib.rs"));
+    assert!(!log.contains("wrapper -vV"));
+
+    let output = freight(&project)?
+        .arg("doc")
+        .env("RUSTDOC", project.root().join("bin/rustdoc"))
+        .output()?;
+    assert!(output.status.success());
+    assert!(invocations(&project)?.contains("env-rustdoc src/lib.rs"));
+    Ok(())
+}
+
+#[test]
+fn rustc_from_the_config() -> Result<()> {
+    let project = Project::new("rustc_from_the_config")?
+        .file("Freight.toml", TOML)?
+        .file("src/lib.rs", LIB)?
+        .file(".freight/config.toml", "[build]\nrustc = \"tools/rustc\"\n")?;
+    let project = fake(project, "tools/rustc", "config-rustc", "rustc")?;
+    let output = freight(&project)?.arg("build").output()?;
+    assert!(output.status.success());
+    assert!(invocations(&project)?.contains("config-rustc src/lib.rs"));
+
+    // The environment wins over the config
+    fs::remove_file(project.root().join("invocations"))?;
+    fs::remove_dir_all(project.root().join("target"))?;
+    let output = freight(&project)?
+        .arg("build")
+        .env("RUSTC", "rustc")
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(invocations(&project)?, "");
+    Ok(())
+}
+
+#[test]
+fn rustup_toolchains() -> Result<()> {
+    let project = Project::new("rustup_toolchains")?
+        .file("Freight.toml", TOML)?
+        .file("src/lib.rs", LIB)?
+        .file(
+            "rustup/toolchains/fake-x86_64-unknown-linux-gnu/lib/rustlib/components",
+            "rustc-x86_64-unknown-linux-gnu\nrust-std-x86_64-unknown-linux-gnu\n",
+        )?;
+    let rustc = real("rustc")?.display().to_string();
+    let project = fake(
+        project,
+        "rustup/toolchains/fake-x86_64-unknown-linux-gnu/bin/rustc",
+        "fake-rustc",
+        &rustc,
+    )?;
+    let rustup_home = project.root().join("rustup");
+
+    let output = freight(&project)?
+        .arg("+fake")
+        .arg("build")
+        .env("RUSTUP_HOME", &rustup_home)
+        .output()?;
+    assert!(output.status.success());
+    assert!(invocations(&project)?.contains("fake-rustc src/lib.rs"));
+
+    let output = freight(&project)?
+        .arg("+missing")
+        .arg("build")
+        .env("RUSTUP_HOME", &rustup_home)
+        .output()?;
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "error: Toolchain missing is not installed\n"
+    );
+
+    // The toolchain file picks the channel when there's no `+toolchain`
+    fs::remove_file(project.root().join("invocations"))?;
+    fs::remove_dir_all(project.root().join("target"))?;
+    let project = project.file(
+        "rust-toolchain.toml",
+        "[toolchain]\nchannel = \"fake\"\ncomponents = [\"rust-std\"]\n",
+    )?;
+    let output = freight(&project)?
+        .arg("build")
+        .env("RUSTUP_HOME", &rustup_home)
+        .output()?;
+    assert!(output.status.success());
+    assert!(invocations(&project)?.contains("fake-rustc src/lib.rs"));
+
+    let project = project.file(
+        "rust-toolchain.toml",
+        "[toolchain]\nchannel = \"fake\"\ncomponents = [\"rust-src\"]\n",
+    )?;
+    let output = freight(&project)?
+        .arg("build")
+        .env("RUSTUP_HOME", &rustup_home)
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains(
+        "Toolchain fake is missing the rust-src component, \
+         install it with `rustup component add rust-src --toolchain fake`"
+    ));
+    Ok(())
+}
+
+#[test]
+fn rustup_host_toolchain_wins() -> Result<()> {
+    let project = Project::new("rustup_host_toolchain_wins")?
+        .file("Freight.toml", TOML)?
+        .file("src/lib.rs", LIB)?
+        .file(
+            "rustup/settings.toml",
+            "default_host_triple = \"x86_64-unknown-linux-gnu\"\n",
+        )?;
+    let rustc = real("rustc")?.display().to_string();
+    let project = fake(
+        project,
+        "rustup/toolchains/fake-aarch64-apple-darwin/bin/rustc",
+        "darwin-rustc",
+        &rustc,
+    )?;
+    let project = fake(
+        project,
+        "rustup/toolchains/fake-x86_64-unknown-linux-gnu/bin/rustc",
+        "linux-rustc",
+        &rustc,
+    )?;
+    let rustup_home = project.root().join("rustup");
+
+    let output = freight(&project)?
+        .arg("+fake")
+        .arg("build")
+        .env("RUSTUP_HOME", &rustup_home)
+        .output()?;
+    assert!(output.status.success());
+    assert!(invocations(&project)?.contains("linux-rustc src/lib.rs"));
+
+    // Neither is for the host, so there's no telling which was meant
+    let project = project.file(
+        "rustup/settings.toml",
+        "default_host_triple = \"riscv64gc-unknown-linux-gnu\"\n",
+    )?;
+    let output = freight(&project)?
+        .arg("+fake")
+        .arg("build")
+        .env("RUSTUP_HOME", &rustup_home)
+        .output()?;
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "error: Toolchain fake is ambiguous, it could be any of \
+         fake-aarch64-apple-darwin, fake-x86_64-unknown-linux-gnu\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn missing_rustdoc_is_named() -> Result<()> {
+    let project = Project::new("missing_rustdoc_is_named")?
+        .file("Freight.toml", TOML)?
+        .file("src/lib.rs", LIB)?;
+    let missing = project.root().join("bin/rustdoc");
+    let output = freight(&project)?
+        .arg("doc")
+        .env("RUSTDOC", &missing)
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains(&format!("error: I/O error for {}\n", missing.display())));
+    assert!(stderr.contains("  caused by: No such file or directory"));
+    Ok(())
+}
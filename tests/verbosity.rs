@@ -0,0 +1,73 @@
+mod common;
+
+use common::Project;
+use common::Result;
+
+fn project(name: &str) -> Result<Project> {
+    Project::new(name)?
+        .file("Freight.toml", "name = \"chatty\"\nedition = \"2021\"\n")?
+        .file("src/lib.rs", "pub fn chatty() {\n    let unused = 1;\n}\n")?
+        .file("src/main.rs", "fn main() {\n    chatty::chatty();\n}\n")
+}
+
+#[test]
+fn verbose_shows_commands_and_fresh_units() -> Result<()> {
+    let project = project("verbose_shows_commands_and_fresh_units")?;
+    let output = project.freight()?.args(["-v", "build"]).output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("   Compiling lib chatty\n"));
+    assert!(stderr.contains("     Running `"));
+    assert!(stderr.contains(" --crate-name chatty "));
+
+    // The flag can come after the command too
+    let output = project.freight()?.args(["build", "--verbose"]).output()?;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("       Fresh lib chatty\n"));
+    assert!(stderr.contains("       Fresh bin chatty\n"));
+    assert!(!stderr.contains("Running"));
+
+    let output = project.freight()?.arg("build").output()?;
+    assert!(!String::from_utf8(output.stderr)?.contains("Fresh"));
+    Ok(())
+}
+
+#[test]
+fn quiet_only_shows_errors() -> Result<()> {
+    let project = project("quiet_only_shows_errors")?;
+    let output = project.freight()?.args(["build", "-q"]).output()?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "");
+    assert_eq!(String::from_utf8(output.stderr)?, "");
+
+    let project = project.file("src/main.rs", "fn main() {\n    missing();\n}\n")?;
+    let output = project.freight()?.args(["-q", "build"]).output()?;
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("error[E0425]"));
+    assert!(stderr.contains("error: could not compile `chatty` (bin \"chatty\")"));
+    assert!(!stderr.contains("warning"));
+
+    let output = project.freight()?.args(["-q", "-v", "build"]).output()?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn finished_and_running_lines() -> Result<()> {
+    let project = project("finished_and_running_lines")?;
+    let output = project.freight()?.arg("build").output()?;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("    Finished dev [unoptimized] target(s) in "));
+
+    let output = project
+        .freight()?
+        .args(["run", "--", "one", "two words"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("    Finished dev [unoptimized] target(s) in "));
+    assert!(stderr.contains("     Running target/debug/chatty one 'two words'\n"));
+    Ok(())
+}